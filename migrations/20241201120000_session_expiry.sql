@@ -0,0 +1,11 @@
+-- The last time a session has been used. Sessions, that have not been used
+-- for a while, expire
+ALTER TABLE session ADD COLUMN last_active INT NOT NULL DEFAULT 0;
+
+-- The login count of the last successful login. Every login has to provide a
+-- higher count, so that replayed logins get rejected
+ALTER TABLE character ADD COLUMN last_login_count INT NOT NULL DEFAULT 0;
+
+-- A character can only have a single active session
+DELETE FROM session WHERE id NOT IN (SELECT max(id) FROM session GROUP BY pid);
+CREATE UNIQUE INDEX session_pid ON session (pid);
@@ -0,0 +1,42 @@
+-- no-transaction
+-- guild.world_id referenced world (id), which does not exist. SQLite can not
+-- change constraints in place, so the table has to be rebuilt. Foreign keys
+-- have to be off for that, otherwise dropping the old table would cascade to
+-- the guild members
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE guild_new (
+  id INTEGER PRIMARY KEY autoincrement NOT NULL,
+  world_id INT NOT NULL DEFAULT 1 REFERENCES world (world_id) ON DELETE cascade,
+
+  name TEXT NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  emblem TEXT NOT NULL,
+
+  raid INT NOT NULL DEFAULT 0,
+  honor INT NOT NULL DEFAULT 200,
+  created INT NOT NULL,
+
+  demon_portal_act INT NOT NULL DEFAULT 1,
+  demon_portal_health INT NOT NULL DEFAULT 1,
+
+  catapult INT NOT NULL DEFAULT 0 CHECK (catapult < 4),
+  attacking INT REFERENCES guild (id),
+
+  pet_id INT,
+  hydra_heads INT,
+  hydra_current_life INT NOT NULL,
+  UNIQUE (world_id, name)
+);
+
+INSERT INTO guild_new SELECT * FROM guild;
+DROP TABLE guild;
+ALTER TABLE guild_new RENAME TO guild;
+
+CREATE index guild_hof ON guild (world_id, honor DESC, id ASC);
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,3 @@
+-- The thirst for adventure, that has been deducted for the current activity.
+-- Cancelling a quest refunds exactly this amount
+ALTER TABLE activity ADD COLUMN thirst_spent INT NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- The wares of the weapon (typ 3) and magic shop (typ 4) of every character
+CREATE TABLE shop (
+  pid INT NOT NULL REFERENCES character (pid) ON DELETE CASCADE,
+  typ INT NOT NULL,
+  item1 INT REFERENCES item (id) ON DELETE SET NULL,
+  item2 INT REFERENCES item (id) ON DELETE SET NULL,
+  item3 INT REFERENCES item (id) ON DELETE SET NULL,
+  item4 INT REFERENCES item (id) ON DELETE SET NULL,
+  item5 INT REFERENCES item (id) ON DELETE SET NULL,
+  item6 INT REFERENCES item (id) ON DELETE SET NULL,
+  PRIMARY KEY (pid, typ)
+);
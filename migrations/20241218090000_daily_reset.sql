@@ -0,0 +1,16 @@
+-- The start of the last day, that the daily reset has been done for. Each
+-- character is reset on its own, so a failed reset can pick up where it
+-- stopped
+ALTER TABLE character ADD COLUMN last_daily_reset INT NOT NULL DEFAULT 0;
+-- When the next arena fight is free again. Fights before that cost a mushroom
+ALTER TABLE character ADD COLUMN arena_next_free INT NOT NULL DEFAULT 0;
+
+-- The daily tasks of every character. They are rolled again every day
+CREATE TABLE daily_task (
+  pid INT NOT NULL REFERENCES character (pid) ON DELETE CASCADE,
+  pos INT NOT NULL,
+  typ INT NOT NULL,
+  progress INT NOT NULL DEFAULT 0,
+  target INT NOT NULL,
+  PRIMARY KEY (pid, pos)
+);
@@ -0,0 +1,228 @@
+-- The Postgres version of the schema. Every integer is a BIGINT, so that the
+-- query macros produce the same (i64) types as for SQLite. Unlike SQLite,
+-- Postgres wants tables to exist before they are referenced, so the order
+-- differs a bit
+
+-- This can be something like w1, w2, etc. You could and probably should have
+-- these be actual distinct instances of the server. Since that is way more
+-- annoying to setup though, this will be all in one server. That also allows
+-- creating private world at a later point in time
+CREATE TABLE world (
+  world_id BIGSERIAL PRIMARY KEY,
+  ident TEXT NOT NULL UNIQUE
+);
+
+-- Add a default world
+INSERT INTO world (ident) VALUES ('');
+
+CREATE TABLE guild (
+  id BIGSERIAL PRIMARY KEY,
+  world_id BIGINT NOT NULL DEFAULT 1 REFERENCES world (world_id) ON DELETE cascade,
+
+  name TEXT NOT NULL,
+  description TEXT NOT NULL DEFAULT '',
+  emblem TEXT NOT NULL,
+
+  raid BIGINT NOT NULL DEFAULT 0,
+  honor BIGINT NOT NULL DEFAULT 200,
+  created BIGINT NOT NULL,
+
+  demon_portal_act BIGINT NOT NULL DEFAULT 1,
+  demon_portal_health BIGINT NOT NULL DEFAULT 1,
+
+  catapult BIGINT NOT NULL DEFAULT 0 CHECK (catapult < 4),
+  attacking BIGINT REFERENCES guild (id),
+
+  pet_id BIGINT,
+  hydra_heads BIGINT,
+  hydra_current_life BIGINT NOT NULL,
+  UNIQUE (world_id, name)
+);
+
+CREATE index guild_hof ON guild (world_id, honor DESC, id ASC);
+
+-- The guild upgrades a character has for himself
+CREATE TABLE guild_upgrade (
+  pid BIGINT PRIMARY KEY,
+  treasure BIGINT NOT NULL DEFAULT 0,
+  instructor BIGINT NOT NULL DEFAULT 0,
+  petlvl BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE item (
+  id BIGSERIAL PRIMARY KEY,
+  enchantment BIGINT NOT NULL DEFAULT 0,
+  item_type BIGINT NOT NULL,
+  effect1 BIGINT NOT NULL DEFAULT 0,
+  effect2 BIGINT NOT NULL DEFAULT 0,
+  ident BIGINT NOT NULL DEFAULT 0,
+  count BIGINT NOT NULL DEFAULT 0,
+  expires BIGINT,
+  gem_type BIGINT NOT NULL DEFAULT 0,
+  gem_power BIGINT NOT NULL DEFAULT 0,
+  class BIGINT NOT NULL DEFAULT 0,
+  atr_typ1 BIGINT NOT NULL DEFAULT 0,
+  atr_val1 BIGINT NOT NULL DEFAULT 0,
+  atr_typ2 BIGINT NOT NULL DEFAULT 0,
+  atr_val2 BIGINT NOT NULL DEFAULT 0,
+  atr_typ3 BIGINT NOT NULL DEFAULT 0,
+  atr_val3 BIGINT NOT NULL DEFAULT 0,
+  model_id BIGINT NOT NULL,
+  silver BIGINT NOT NULL,
+  mushrooms BIGINT NOT NULL
+);
+
+CREATE TABLE bag (
+  pid BIGINT PRIMARY KEY,
+  pos1 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  pos2 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  pos3 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  pos4 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  pos5 BIGINT REFERENCES item (id) ON DELETE SET NULL
+);
+
+CREATE TABLE attributes (
+  id BIGSERIAL PRIMARY KEY,
+  strength BIGINT NOT NULL DEFAULT 0,
+  dexterity BIGINT NOT NULL DEFAULT 0,
+  intelligence BIGINT NOT NULL DEFAULT 0,
+  stamina BIGINT NOT NULL DEFAULT 0,
+  luck BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE quest (
+  id BIGSERIAL PRIMARY KEY,
+  flavour1 BIGINT NOT NULL DEFAULT 1,
+  flavour2 BIGINT NOT NULL DEFAULT 1,
+  monster BIGINT NOT NULL,
+  location BIGINT NOT NULL,
+  length BIGINT NOT NULL,
+  xp BIGINT NOT NULL,
+  silver BIGINT NOT NULL,
+  mushrooms BIGINT NOT NULL DEFAULT 0,
+  item BIGINT REFERENCES item (id) ON DELETE SET NULL
+);
+
+CREATE TABLE activity (
+  pid BIGINT PRIMARY KEY,
+  typ BIGINT NOT NULL DEFAULT 0,
+  sub_type BIGINT NOT NULL DEFAULT 0,
+  started BIGINT NOT NULL DEFAULT 0,
+  busy_until BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE tavern (
+  pid BIGSERIAL PRIMARY KEY,
+  quest1 BIGINT NOT NULL REFERENCES quest (id),
+  quest2 BIGINT NOT NULL REFERENCES quest (id),
+  quest3 BIGINT NOT NULL REFERENCES quest (id),
+  tfa BIGINT NOT NULL DEFAULT 6000,
+  beer_drunk BIGINT NOT NULL DEFAULT 0,
+  quicksand BIGINT NOT NULL DEFAULT 60,
+  dice_games_remaining BIGINT NOT NULL DEFAULT 10,
+  dice_game_next_free BIGINT NOT NULL DEFAULT 0
+);
+
+-- This is only character equipment
+CREATE TABLE equipment (
+  pid BIGINT PRIMARY KEY,
+  hat BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  breastplate BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  gloves BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  footwear BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  amulet BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  belt BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  ring BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  talisman BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  weapon BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  shield BIGINT REFERENCES item (id) ON DELETE SET NULL
+);
+
+CREATE TABLE portrait (
+  pid BIGINT PRIMARY KEY,
+  mouth BIGINT NOT NULL DEFAULT 1,
+  hair BIGINT NOT NULL DEFAULT 1,
+  brows BIGINT NOT NULL DEFAULT 1,
+  eyes BIGINT NOT NULL DEFAULT 1,
+  beards BIGINT NOT NULL DEFAULT 1,
+  nose BIGINT NOT NULL DEFAULT 1,
+  ears BIGINT NOT NULL DEFAULT 1,
+  extra BIGINT NOT NULL DEFAULT 1,
+  horns BIGINT NOT NULL DEFAULT 1,
+  influencer BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE character (
+  pid BIGINT PRIMARY KEY,
+  world_id BIGINT NOT NULL REFERENCES world (world_id) ON DELETE cascade,
+
+  crypto_key TEXT NOT NULL,
+  mail TEXT UNIQUE,
+  pw_hash TEXT NOT NULL,
+
+  name TEXT NOT NULL,
+  class BIGINT NOT NULL,
+  race BIGINT NOT NULL,
+  gender BIGINT NOT NULL,
+  level BIGINT NOT NULL DEFAULT 1,
+  experience BIGINT NOT NULL DEFAULT 0,
+  honor BIGINT NOT NULL DEFAULT 300,
+  silver BIGINT NOT NULL DEFAULT 100,
+  mushrooms BIGINT NOT NULL DEFAULT 30,
+  description TEXT NOT NULL DEFAULT '',
+  mount BIGINT NOT NULL DEFAULT 0,
+  mount_end BIGINT NOT NULL DEFAULT 0,
+  tutorial_status BIGINT NOT NULL DEFAULT 0,
+
+  attributes BIGINT NOT NULL REFERENCES attributes (id),
+  attributes_bought BIGINT NOT NULL REFERENCES attributes (id),
+
+  FOREIGN KEY (pid) REFERENCES guild_upgrade (pid),
+  FOREIGN KEY (pid) REFERENCES equipment (pid),
+  FOREIGN KEY (pid) REFERENCES activity (pid),
+  FOREIGN KEY (pid) REFERENCES portrait (pid),
+  FOREIGN KEY (pid) REFERENCES bag (pid),
+  FOREIGN KEY (pid) REFERENCES tavern (pid),
+
+  UNIQUE (name, world_id)
+);
+
+CREATE index character_hof ON character(world_id, honor DESC, pid ASC);
+
+-- Everything related to the Membership of a character to a guild
+CREATE TABLE guild_member (
+  pid BIGINT PRIMARY KEY,
+  guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+  -- 1 => Leader
+  -- 2 => Member
+  -- 3 => Leader
+  rank BIGINT NOT NULL CHECK (rank < 4),
+  joined BIGINT NOT NULL,
+  last_active BIGINT NOT NULL,
+
+  is_defending BOOL NOT NULL DEFAULT FALSE,
+  is_attacking BOOL NOT NULL DEFAULT FALSE,
+
+  hydra_fought BOOL NOT NULL DEFAULT FALSE,
+  portal_fought BOOL NOT NULL DEFAULT FALSE,
+
+  FOREIGN KEY (pid) REFERENCES character (pid)
+);
+
+CREATE TABLE session (
+  id BIGSERIAL PRIMARY KEY,
+  pid BIGINT NOT NULL REFERENCES character (pid) ON DELETE CASCADE,
+  session_id TEXT NOT NULL,
+  crypto_id TEXT UNIQUE NOT NULL,
+  login_count BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE chat_message (
+  id BIGSERIAL PRIMARY KEY,
+  sender BIGINT REFERENCES character(pid) ON DELETE CASCADE,
+  time BIGINT NOT NULL,
+  guild BIGINT REFERENCES guild (id),
+  whisper BIGINT REFERENCES character(pid),
+  message TEXT NOT NULL,
+  is_global BOOL NOT NULL
+);
@@ -0,0 +1,6 @@
+-- guild.world_id has to reference world (world_id), like it does in SQLite.
+-- The constraint is recreated, so that it is guaranteed to point at the
+-- right column with the right name, regardless of how the table was created
+ALTER TABLE guild DROP CONSTRAINT IF EXISTS guild_world_id_fkey;
+ALTER TABLE guild ADD CONSTRAINT guild_world_id_fkey
+  FOREIGN KEY (world_id) REFERENCES world (world_id) ON DELETE cascade;
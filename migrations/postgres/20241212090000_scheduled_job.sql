@@ -0,0 +1,6 @@
+-- The last time every job of the scheduler has run. Jobs, that were missed
+-- while the server was down, are caught up on once it is started again
+CREATE TABLE scheduled_job (
+  name TEXT PRIMARY KEY,
+  last_run BIGINT NOT NULL
+);
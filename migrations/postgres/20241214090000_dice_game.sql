@@ -0,0 +1,7 @@
+-- The dice of the current dice game, separated by slashes. Empty, if no game
+-- is running
+ALTER TABLE tavern ADD COLUMN dice_status TEXT NOT NULL DEFAULT '';
+
+-- Fortress resources, that can already be won in the dice game
+ALTER TABLE character ADD COLUMN wood BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE character ADD COLUMN stone BIGINT NOT NULL DEFAULT 0;
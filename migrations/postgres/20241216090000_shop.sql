@@ -0,0 +1,12 @@
+-- The wares of the weapon (typ 3) and magic shop (typ 4) of every character
+CREATE TABLE shop (
+  pid BIGINT NOT NULL REFERENCES character (pid) ON DELETE CASCADE,
+  typ BIGINT NOT NULL,
+  item1 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  item2 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  item3 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  item4 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  item5 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  item6 BIGINT REFERENCES item (id) ON DELETE SET NULL,
+  PRIMARY KEY (pid, typ)
+);
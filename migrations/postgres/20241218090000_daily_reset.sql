@@ -0,0 +1,16 @@
+-- The start of the last day, that the daily reset has been done for. Each
+-- character is reset on its own, so a failed reset can pick up where it
+-- stopped
+ALTER TABLE character ADD COLUMN last_daily_reset BIGINT NOT NULL DEFAULT 0;
+-- When the next arena fight is free again. Fights before that cost a mushroom
+ALTER TABLE character ADD COLUMN arena_next_free BIGINT NOT NULL DEFAULT 0;
+
+-- The daily tasks of every character. They are rolled again every day
+CREATE TABLE daily_task (
+  pid BIGINT NOT NULL REFERENCES character (pid) ON DELETE CASCADE,
+  pos BIGINT NOT NULL,
+  typ BIGINT NOT NULL,
+  progress BIGINT NOT NULL DEFAULT 0,
+  target BIGINT NOT NULL,
+  PRIMARY KEY (pid, pos)
+);
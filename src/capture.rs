@@ -0,0 +1,148 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+
+use log::{error, info};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use sf_api::misc::{sha1_hash, HASH_CONST};
+
+use crate::{config::get_config, ServerError, ServerResponse};
+
+/// A single command, as we received it, together with the response we sent.
+/// A capture file contains one of these per line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedCommand {
+    pub command: String,
+    pub args: Vec<String>,
+    /// The pid of the session, that sent this. Negative if not logged in
+    pub pid: i64,
+    pub world_id: i64,
+    /// The seed of the thread local rng, while the command was handled
+    pub seed: u64,
+    pub response: String,
+}
+
+/// The password of every account in a capture file. Captures must not
+/// contain the real credentials, but still have to be replayable
+pub const REDACTED_PASSWORD: &str = "redacted";
+
+/// The arguments of the command with all passwords, password hashes and
+/// mail addresses replaced. Passwords become `REDACTED_PASSWORD` and the
+/// hashes are recomputed for it, so logins still work when replayed
+pub fn redacted_args(command: &str, args: &[&str]) -> Vec<String> {
+    let mut args: Vec<String> = args.iter().map(ToString::to_string).collect();
+    let expected_len = match command {
+        "AccountCreate" => 7,
+        "AccountLogin" => 3,
+        "AccountDelete" => 4,
+        "PlayerWhisper" => {
+            if args.get(1).is_some_and(|a| a.starts_with("set_password")) {
+                args[1] = format!("set_password {REDACTED_PASSWORD}");
+            }
+            return args;
+        }
+        _ => return args,
+    };
+    if args.len() != expected_len {
+        // A password containing a '/' would end up in the wrong arguments
+        for arg in args.iter_mut().skip(1) {
+            *arg = "<redacted>".to_string();
+        }
+        return args;
+    }
+    match command {
+        "AccountCreate" => {
+            args[1] = REDACTED_PASSWORD.to_string();
+            args[2] = redacted_mail(&args[2]);
+        }
+        _ => {
+            args[1] = redacted_hash(&args[2]);
+            if command == "AccountDelete" {
+                args[3] = redacted_mail(&args[3]);
+            }
+        }
+    }
+    args
+}
+
+/// The login hash of `REDACTED_PASSWORD` with the given login count
+fn redacted_hash(login_count: &str) -> String {
+    let pw_hash = sha1_hash(&format!("{REDACTED_PASSWORD}{HASH_CONST}"));
+    sha1_hash(&format!("{pw_hash}{login_count}"))
+}
+
+/// Mails have to stay unique, so they are replaced with a hash of the real
+/// one
+fn redacted_mail(mail: &str) -> String {
+    format!("{}@redacted.invalid", &sha1_hash(mail)[..16])
+}
+
+fn capture_file() -> Option<&'static Mutex<File>> {
+    static CAPTURE: OnceCell<Option<Mutex<File>>> = OnceCell::new();
+    CAPTURE
+        .get_or_init(|| {
+            let path = get_config().capture.file.as_deref()?;
+            match open_capture_file(path) {
+                Ok(file) => {
+                    info!("Capturing all commands to {path:?}");
+                    Some(Mutex::new(file))
+                }
+                Err(e) => {
+                    error!("Could not open capture file {path:?}: {e}");
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+fn open_capture_file(path: &Path) -> std::io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Whether or not commands should be recorded
+pub fn is_capturing() -> bool {
+    capture_file().is_some()
+}
+
+/// Picks the seed for the next captured command and seeds the thread local
+/// rng with it. Handlers have to create their `Rng` before the first
+/// `.await` for this to make them reproducible
+pub fn seed_rng() -> u64 {
+    let seed = fastrand::u64(..);
+    fastrand::seed(seed);
+    seed
+}
+
+/// The body we send to the client for the result of a command
+pub fn response_body(res: &Result<ServerResponse, ServerError>) -> String {
+    match res {
+        Ok(resp) => resp.body().to_string(),
+        Err(e) => e.body(),
+    }
+}
+
+/// Appends the command to the capture file
+pub fn record(command: &CapturedCommand) {
+    let Some(file) = capture_file() else {
+        return;
+    };
+    let line = match serde_json::to_string(command) {
+        Ok(line) => line,
+        Err(e) => {
+            error!("Could not serialize captured command: {e}");
+            return;
+        }
+    };
+    let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = writeln!(file, "{line}") {
+        error!("Could not write to capture file: {e}");
+    }
+}
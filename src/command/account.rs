@@ -1,27 +1,40 @@
-use command::{poll, CommandArguments, Portrait};
+use command::{
+    generate_quest, insert_quest, now, poll, reroll_shop, roll_daily_tasks,
+    Portrait, MAGIC_SHOP, WEAPON_SHOP,
+};
 use fastrand::Rng;
+use log::warn;
 use num_traits::FromPrimitive;
 use request::Session;
 use sf_api::{
     gamestate::character::{Class, Gender, Race},
     misc::{sha1_hash, HASH_CONST},
 };
-use sqlx::Sqlite;
 
-use crate::{misc::OptionGet, *};
+use crate::{db::DbPool, *};
+
+command_args! {
+    pub(crate) struct AccountCheckArgs {
+        name: String,
+    }
+}
 
 pub(crate) async fn account_check(
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    session: Session,
+    db: &DbPool,
+    args: AccountCheckArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let name = args.get_str(0, "name")?;
+    let name = args.name.as_str();
 
     if is_invalid_name(name) {
         return Err(ServerError::InvalidName)?;
     }
 
     let count = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM CHARACTER WHERE name = $1", name
+        "SELECT COUNT(*) as \"count!\" FROM CHARACTER WHERE lower(name) = \
+         lower($1) AND world_id = $2",
+        name,
+        session.world_id
     )
     .fetch_one(db)
     .await?;
@@ -38,31 +51,52 @@ pub(crate) async fn account_check(
     }
 }
 
+command_args! {
+    pub(crate) struct AccountCreateArgs {
+        name: String,
+        password: String,
+        mail: String,
+        gender: i64,
+        race: i64,
+        class: i64,
+        portrait: String,
+    }
+}
+
 pub(crate) async fn account_create(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: AccountCreateArgs,
 ) -> Result<ServerResponse, ServerError> {
     let mut rng = Rng::new();
-    let name = args.get_str(0, "name")?;
-    let password = args.get_str(1, "password")?;
-    let mail = args.get_str(2, "mail")?;
-    let gender = args.get_int(3, "gender")?;
-    Gender::from_i64(gender.saturating_sub(1)).get("gender")?;
-    let race = args.get_int(4, "race")?;
-    Race::from_i64(race).get("race")?;
-
-    let class = args.get_int(5, "class")?;
-    let _class = Class::from_i64(class.saturating_sub(1)).get("class")?;
-
-    let portrait_str = args.get_str(6, "portrait")?;
-    let portrait = Portrait::parse(portrait_str).get("portrait")?;
+    let AccountCreateArgs {
+        name,
+        password,
+        mail,
+        gender,
+        race,
+        class,
+        portrait,
+    } = args;
+    let name = name.as_str();
+    Gender::from_i64(gender.saturating_sub(1))
+        .ok_or(ServerError::InvalidArgument("gender"))?;
+    Race::from_i64(race).ok_or(ServerError::InvalidArgument("race"))?;
+    Class::from_i64(class.saturating_sub(1))
+        .ok_or(ServerError::InvalidArgument("class"))?;
+    let portrait = Portrait::parse(&portrait)
+        .ok_or(ServerError::InvalidArgument("portrait"))?;
 
     if is_invalid_name(name) {
         Err(ServerError::InvalidName)?;
     }
+    if password.len() < 6 {
+        Err(ServerError::PasswordTooShort)?;
+    }
+    if is_invalid_mail(&mail) {
+        Err(ServerError::InvalidMail)?;
+    }
 
-    // TODO: Do some more input validation
     let hashed_password = sha1_hash(&format!("{password}{}", HASH_CONST));
 
     let mut crypto_id = "0-".to_string();
@@ -81,23 +115,31 @@ pub(crate) async fn account_create(
 
     let mut tx = db.begin().await?;
 
+    let taken = sqlx::query!(
+        "SELECT
+            (SELECT COUNT(*) FROM character
+                WHERE lower(name) = lower($1) AND world_id = $2)
+                as \"name_taken!: i64\",
+            (SELECT COUNT(*) FROM character WHERE mail = $3)
+                as \"mail_taken!: i64\"",
+        name,
+        session.world_id,
+        mail
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if taken.name_taken > 0 {
+        Err(ServerError::CharacterExists)?;
+    }
+    if taken.mail_taken > 0 {
+        Err(ServerError::MailTaken)?;
+    }
+
     let mut quests = [0; 3];
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..3 {
-        let res = sqlx::query_scalar!(
-            "INSERT INTO QUEST (monster, location, length, xp, silver, \
-             mushrooms)
-                    VALUES ($1, $2, $3, $4, $5, $6) returning ID",
-            139,
-            1,
-            60,
-            100,
-            100,
-            1,
-        )
-        .fetch_one(&mut *tx)
-        .await?;
-        quests[i] = res;
+    for quest in &mut quests {
+        *quest = insert_quest(&mut tx, &generate_quest(&mut rng, 1, class))
+            .await?;
     }
 
     let pid = sqlx::query_scalar!(
@@ -169,10 +211,14 @@ pub(crate) async fn account_create(
         .execute(&mut *tx)
         .await?;
 
+    // The character starts with everything the daily reset would give it, so
+    // it only gets reset on the next day
+    let now = now();
     sqlx::query!(
         "INSERT INTO character (pid, world_id, pw_hash, name, class, race, \
-         gender, attributes, attributes_bought, mail, crypto_key)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+         gender, attributes, attributes_bought, mail, crypto_key, \
+         last_daily_reset)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
         pid,
         session.world_id,
         hashed_password,
@@ -183,14 +229,24 @@ pub(crate) async fn account_create(
         attr_id,
         attr_upgrades,
         mail,
-        crypto_key
+        crypto_key,
+        now
     )
     .execute(&mut *tx)
     .await?;
+    // The wares and tasks belong to the character, so it has to exist first
+    for shop in [WEAPON_SHOP, MAGIC_SHOP] {
+        reroll_shop(&mut tx, &mut rng, pid, 1, class, shop).await?;
+    }
+    roll_daily_tasks(&mut tx, &mut rng, pid).await?;
 
     sqlx::query!(
-        "INSERT INTO SESSION (pid, session_id, crypto_id) VALUES ($1, $2, $3)",
-        pid, session_id, crypto_id,
+        "INSERT INTO SESSION (pid, session_id, crypto_id, last_active) VALUES \
+         ($1, $2, $3, $4)",
+        pid,
+        session_id,
+        crypto_id,
+        now,
     )
     .execute(&mut *tx)
     .await?;
@@ -203,28 +259,41 @@ pub(crate) async fn account_create(
         .build()
 }
 
+command_args! {
+    pub(crate) struct AccountDeleteArgs {
+        name: String,
+        pw_hash: String,
+        login_count: i64,
+        mail: String,
+    }
+}
+
 pub(crate) async fn account_delete(
-    _session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    session: Session,
+    db: &DbPool,
+    args: AccountDeleteArgs,
 ) -> Result<ServerResponse, ServerError> {
     if true {
         return Ok(ServerResponse::Success);
     }
 
-    let name = args.get_str(0, "account name")?;
-    let full_hash = args.get_str(1, "pw hash")?;
-    let login_count = args.get_int(2, "login count")?;
-    let mail = args.get_str(3, "account mail")?;
+    let AccountDeleteArgs {
+        name,
+        pw_hash: full_hash,
+        login_count,
+        mail,
+    } = args;
 
     let mut tx = db.begin().await?;
 
     let res = sqlx::query!(
         "SELECT pid, pw_hash
                     FROM character
-                    WHERE lower(name) = lower($1) and mail = $2",
+                    WHERE lower(name) = lower($1) and mail = $2
+                    AND world_id = $3",
         name,
         mail,
+        session.world_id,
     )
     .fetch_optional(&mut *tx)
     .await?;
@@ -250,26 +319,41 @@ pub(crate) async fn account_delete(
     Ok(ServerResponse::Success)
 }
 
+command_args! {
+    pub(crate) struct AccountLoginArgs {
+        name: String,
+        pw_hash: String,
+        login_count: i64,
+    }
+}
+
 pub(crate) async fn account_login(
     mut session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: AccountLoginArgs,
 ) -> Result<ServerResponse, ServerError> {
     let mut rng = Rng::new();
-    let name = args.get_str(0, "name")?;
-    let full_hash = args.get_str(1, "password hash")?;
-    let login_count = args.get_int(2, "login count")?;
+    let AccountLoginArgs {
+        name,
+        pw_hash: full_hash,
+        login_count,
+    } = args;
 
     let mut tx = db.begin().await?;
 
     let info = sqlx::query!(
-        "SELECT pid, pw_hash, crypto_key FROM
-                character WHERE lower(name) = lower($1)",
-        name
+        "SELECT pid, pw_hash, crypto_key, last_login_count FROM
+                character WHERE lower(name) = lower($1) AND world_id = $2",
+        name,
+        session.world_id
     )
-    .fetch_one(&mut *tx)
+    .fetch_optional(&mut *tx)
     .await?;
 
+    let Some(info) = info else {
+        return Err(ServerError::WrongPassword);
+    };
+
     let pid = info.pid;
     let pwhash = info.pw_hash;
 
@@ -278,6 +362,14 @@ pub(crate) async fn account_login(
         Err(ServerError::WrongPassword)?;
     }
 
+    // The client has to increase the login count on every login, so someone
+    // replaying a login request we have already seen can not log in. A client
+    // that lost its count has to continue above the last one
+    if login_count <= info.last_login_count {
+        warn!("Replayed login for {pid} (login count {login_count})");
+        Err(ServerError::LoginCountTooLow)?;
+    }
+
     let session_id: String = (0..DEFAULT_SESSION_ID.len())
         .map(|_| rng.alphanumeric())
         .collect();
@@ -288,12 +380,31 @@ pub(crate) async fn account_login(
         crypto_id.push(rc);
     }
 
+    // Logging in revokes all other sessions of this character
+    sqlx::query!("DELETE FROM session WHERE pid = $1", pid)
+        .execute(&mut *tx)
+        .await?;
+
+    // Every following request has to carry a higher login count, than this
+    // one (see `use_login_count`)
+    let now = now();
     sqlx::query!(
-        "INSERT INTO session (pid, session_id, crypto_id)
-                VALUES ($1, $2, $3)",
+        "INSERT INTO session (pid, session_id, crypto_id, login_count, \
+         last_active)
+                VALUES ($1, $2, $3, $4, $5)",
         pid,
         session_id,
         crypto_id,
+        login_count,
+        now,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE character SET last_login_count = $1 WHERE pid = $2",
+        login_count,
+        pid,
     )
     .execute(&mut *tx)
     .await?;
@@ -304,11 +415,23 @@ pub(crate) async fn account_login(
     session.crypto_key = info.crypto_key;
     session.session_id = session_id;
     session.player_id = pid;
-    session.login_count = 1;
+    session.login_count = login_count;
 
     poll(session, "accountlogin", db, Default::default()).await
 }
 
+fn is_invalid_mail(mail: &str) -> bool {
+    let Some((user, domain)) = mail.split_once('@') else {
+        return true;
+    };
+    user.is_empty()
+        || mail.len() > 100
+        || !domain.contains('.')
+        || domain.starts_with('.')
+        || domain.ends_with('.')
+        || mail.chars().any(|a| a.is_whitespace() || a == '/')
+}
+
 fn is_invalid_name(name: &str) -> bool {
     name.len() < 3
         || name.len() > 20
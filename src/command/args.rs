@@ -0,0 +1,103 @@
+use super::CommandArguments;
+use crate::ServerError;
+
+/// The typed arguments of a command, parsed from the slash separated
+/// arguments the client sends
+pub(crate) trait FromArgs: Sized {
+    fn from_args(args: &CommandArguments<'_>) -> Result<Self, ServerError>;
+}
+
+/// For commands, that do not care about their arguments
+impl FromArgs for () {
+    fn from_args(_args: &CommandArguments<'_>) -> Result<Self, ServerError> {
+        Ok(())
+    }
+}
+
+/// A single positional argument of a command
+pub(crate) trait ArgValue: Sized {
+    fn from_arg(
+        arg: Option<&str>,
+        name: &'static str,
+    ) -> Result<Self, ServerError>;
+}
+
+impl ArgValue for i64 {
+    fn from_arg(
+        arg: Option<&str>,
+        name: &'static str,
+    ) -> Result<Self, ServerError> {
+        arg.ok_or(ServerError::MissingArgument(name))?
+            .parse()
+            .map_err(|_| ServerError::InvalidArgument(name))
+    }
+}
+
+impl ArgValue for String {
+    fn from_arg(
+        arg: Option<&str>,
+        name: &'static str,
+    ) -> Result<Self, ServerError> {
+        arg.map(str::to_string)
+            .ok_or(ServerError::MissingArgument(name))
+    }
+}
+
+/// Optional arguments may be left out, or be empty
+impl<T: ArgValue> ArgValue for Option<T> {
+    fn from_arg(
+        arg: Option<&str>,
+        name: &'static str,
+    ) -> Result<Self, ServerError> {
+        match arg {
+            None | Some("") => Ok(None),
+            arg => T::from_arg(arg, name).map(Some),
+        }
+    }
+}
+
+/// An integer argument, that must be within `MIN..=MAX`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InRange<const MIN: i64, const MAX: i64>(pub i64);
+
+impl<const MIN: i64, const MAX: i64> ArgValue for InRange<MIN, MAX> {
+    fn from_arg(
+        arg: Option<&str>,
+        name: &'static str,
+    ) -> Result<Self, ServerError> {
+        let val = i64::from_arg(arg, name)?;
+        if !(MIN..=MAX).contains(&val) {
+            return Err(ServerError::InvalidArgument(name));
+        }
+        Ok(Self(val))
+    }
+}
+
+/// Declares the arguments struct of a command. The fields are parsed in
+/// order from the positional arguments, so the declaration order has to
+/// match the order, in which the client sends them
+macro_rules! command_args {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident {
+        $($(#[$field_meta:meta])* $field:ident: $ty:ty),* $(,)?
+    }) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        $vis struct $name {
+            $($(#[$field_meta])* pub $field: $ty,)*
+        }
+
+        impl $crate::command::FromArgs for $name {
+            fn from_args(
+                args: &$crate::command::CommandArguments<'_>,
+            ) -> Result<Self, $crate::ServerError> {
+                let mut args = args.0.iter().copied();
+                Ok(Self {
+                    $($field: $crate::command::ArgValue::from_arg(
+                        args.next(),
+                        stringify!($field),
+                    )?,)*
+                })
+            }
+        }
+    };
+}
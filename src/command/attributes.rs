@@ -0,0 +1,119 @@
+use super::{update::poll, InRange, ServerError, ServerResponse};
+use crate::{db::DbPool, request::Session};
+
+/// The columns of the `attributes` table in the order, that the client uses
+/// for its attribute ids (starting at 1)
+const ATTRIBUTE_COLUMNS: [&str; 5] =
+    ["strength", "dexterity", "intelligence", "stamina", "luck"];
+
+/// How many points of an attribute can be bought with a single request
+const MAX_POINTS_PER_REQUEST: i64 = 1000;
+
+/// No single attribute point costs more than 10 million silver. Like all
+/// prices, this is in copper (1/100 silver), the unit `character.silver` is
+/// stored in
+const MAX_PRICE: i64 = 10_000_000 * 100;
+
+/// What the next point of an attribute costs (in copper), once `bought`
+/// points of it have already been bought. The price grows with the cube of
+/// the bought points, until it hits `MAX_PRICE`.
+///
+/// NOTE: This is a placeholder, not the official formula. It only keeps the
+/// shape of the official curve (cheap at first, then steeply rising up to a
+/// cap). Once the official prices are known, this has to be replaced and the
+/// tests have to check the official prices of the 1st, 100th and 1000th point
+pub(crate) fn attribute_price(bought: i64) -> i64 {
+    let base = (bought + 1) as f64 / 5.0 + 1.0;
+    ((base.powi(3) * 25.0) as i64).min(MAX_PRICE)
+}
+
+command_args! {
+    pub(crate) struct AttributeIncreaseArgs {
+        /// 1 for strength, up to 5 for luck
+        attribute: InRange<1, 5>,
+        /// The value the attribute should have afterwards
+        increase_to: i64,
+    }
+}
+
+/// Buys points of an attribute, until it reaches the requested value
+pub(crate) async fn player_attribute_increase(
+    session: Session,
+    db: &DbPool,
+    args: AttributeIncreaseArgs,
+) -> Result<ServerResponse, ServerError> {
+    let idx = (args.attribute.0 - 1) as usize;
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query!(
+        "SELECT c.silver, c.attributes, c.attributes_bought,
+                a.strength, a.dexterity, a.intelligence, a.stamina, a.luck,
+                b.strength as bought_strength,
+                b.dexterity as bought_dexterity,
+                b.intelligence as bought_intelligence,
+                b.stamina as bought_stamina,
+                b.luck as bought_luck
+         FROM character c
+         JOIN attributes a ON a.id = c.attributes
+         JOIN attributes b ON b.id = c.attributes_bought
+         WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let current = [
+        row.strength, row.dexterity, row.intelligence, row.stamina, row.luck,
+    ][idx];
+    let bought = [
+        row.bought_strength, row.bought_dexterity, row.bought_intelligence,
+        row.bought_stamina, row.bought_luck,
+    ][idx];
+
+    let points = args.increase_to - current;
+    if !(1..=MAX_POINTS_PER_REQUEST).contains(&points) {
+        return Err(ServerError::BadRequest);
+    }
+    let price: i64 = (bought..bought + points).map(attribute_price).sum();
+    if row.silver < price {
+        return Err(ServerError::NotEnoughMoney);
+    }
+
+    sqlx::query!(
+        "UPDATE character SET silver = silver - $2 WHERE pid = $1",
+        session.player_id, price
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // The base attributes include the bought points, the bought attributes
+    // only count them for the price
+    let column = ATTRIBUTE_COLUMNS[idx];
+    for id in [row.attributes, row.attributes_bought] {
+        sqlx::query(&format!(
+            "UPDATE attributes SET {column} = {column} + $1 WHERE id = $2"
+        ))
+        .bind(points)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_prices() {
+        assert_eq!(attribute_price(0), 43);
+        assert_eq!(attribute_price(1), 68);
+        assert_eq!(attribute_price(99), 25 * 21 * 21 * 21);
+        assert!((0..5000).all(|a| attribute_price(a) <= attribute_price(a + 1)));
+        assert_eq!(attribute_price(10_000), MAX_PRICE);
+    }
+}
@@ -4,10 +4,12 @@ use sf_api::{
     gamestate::character::Class,
     misc::{sha1_hash, HASH_CONST},
 };
-use sqlx::Sqlite;
 
-use super::{update::poll, ServerError, ServerResponse};
-use crate::{misc::OptionGet, request::Session};
+use super::{
+    item::import_items, repair_quests, update::poll, ServerError,
+    ServerResponse,
+};
+use crate::{db::DbPool, misc::OptionGet, request::Session};
 
 #[derive(Debug, Parser)]
 #[command(about, version, no_binary_name(true))]
@@ -31,22 +33,40 @@ enum Command {
     AddWorld {
         world_name: String,
     },
+    /// Repairs characters with broken quests or activities
+    CheckQuests,
+    /// Replaces the equipment and bag with the items in `items/*.json`
+    ImportItems,
 }
 
 pub(crate) async fn handle_cheat_command(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
+    db: &DbPool,
     command: CheatCmd,
 ) -> Result<ServerResponse, ServerError> {
     match command.command {
         Command::AddWorld { world_name } => {
+            // The ident is used as the subdomain of the world, so it has to
+            // be a valid DNS label
+            if world_name.is_empty()
+                || world_name.len() > 63
+                || world_name.starts_with('-')
+                || world_name.ends_with('-')
+                || !world_name
+                    .chars()
+                    .all(|a| a.is_ascii_alphanumeric() || a == '-')
+            {
+                return Err(ServerError::BadRequest);
+            }
+            let world_name = world_name.to_ascii_lowercase();
             sqlx::query!("INSERT INTO world (ident) VALUES ($1)", world_name)
                 .execute(db)
                 .await?;
             return Ok(ServerResponse::Success);
         }
-        _ if session.player_id == -1 => {
-            return Err(ServerError::BadRequest);
+        Command::CheckQuests => {
+            repair_quests(db).await?;
+            return Ok(ServerResponse::Success);
         }
         Command::Level { level } => {
             if level < 1 {
@@ -70,6 +90,9 @@ pub(crate) async fn handle_cheat_command(
             .execute(db)
             .await?;
         }
+        Command::ImportItems => {
+            import_items(db, session.player_id).await?;
+        }
         Command::SetPassword { new } => {
             let hashed_password = sha1_hash(&format!("{new}{}", HASH_CONST));
             let mut tx = db.begin().await?;
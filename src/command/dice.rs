@@ -0,0 +1,199 @@
+use fastrand::Rng;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+use super::{
+    add_experience, in_seconds, now, progress_task, update::poll,
+    xp_for_next_level, InRange, ResponseBuilder, ServerError, ServerResponse,
+    TaskTyp,
+};
+use crate::{db::DbPool, request::Session};
+
+/// How long (in seconds) the character has to wait between free games
+const FREE_GAME_COOLDOWN: i64 = 10 * 60;
+
+/// The symbols on the dice. A die with the value 0 is one, that the client
+/// wants to reroll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+enum DiceSymbol {
+    Silver = 1,
+    Stone = 2,
+    Wood = 3,
+    Mushroom = 4,
+    Experience = 5,
+    Hourglass = 6,
+}
+
+command_args! {
+    pub(crate) struct RollDiceArgs {
+        /// 0 for a free game, 1 to pay with a mushroom and 2 to pay with an
+        /// hourglass. Only the first roll of a game is paid for, so this is
+        /// ignored on the second one
+        price: InRange<0, 2>,
+        die1: InRange<0, 6>,
+        die2: InRange<0, 6>,
+        die3: InRange<0, 6>,
+        die4: InRange<0, 6>,
+        die5: InRange<0, 6>,
+    }
+}
+
+/// Plays the dice game. The first roll of a game rolls all dice and has to
+/// be paid for. The second roll is included in that price. It rerolls the
+/// dice, that the client has set to 0, and pays out the reward for the
+/// symbol, that is on the most dice
+pub(crate) async fn roll_dice(
+    session: Session,
+    db: &DbPool,
+    args: RollDiceArgs,
+) -> Result<ServerResponse, ServerError> {
+    let mut rng = Rng::new();
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query!(
+        "SELECT dice_status, dice_games_remaining, dice_game_next_free,
+                quicksand, mushrooms, level, experience
+         FROM tavern NATURAL JOIN character
+         WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mut resp = ResponseBuilder::default();
+
+    if row.dice_status.is_empty() {
+        match args.price.0 {
+            0 => {
+                if row.dice_games_remaining < 1
+                    || row.dice_game_next_free > now()
+                {
+                    return Err(ServerError::StillBusy);
+                }
+                sqlx::query!(
+                    "UPDATE tavern
+                     SET dice_games_remaining = dice_games_remaining - 1,
+                         dice_game_next_free = $2
+                     WHERE pid = $1",
+                    session.player_id,
+                    in_seconds(FREE_GAME_COOLDOWN)
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            1 => {
+                if row.mushrooms < 1 {
+                    return Err(ServerError::NotEnoughMushrooms);
+                }
+                sqlx::query!(
+                    "UPDATE character SET mushrooms = mushrooms - 1
+                     WHERE pid = $1",
+                    session.player_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            _ => {
+                if row.quicksand < 1 {
+                    return Err(ServerError::NotEnoughHourglasses);
+                }
+                sqlx::query!(
+                    "UPDATE tavern SET quicksand = quicksand - 1
+                     WHERE pid = $1",
+                    session.player_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        let dice: [i64; 5] = std::array::from_fn(|_| rng.i64(1..=6));
+        let status = dice.map(|a| a.to_string()).join("/");
+        sqlx::query!(
+            "UPDATE tavern SET dice_status = $2 WHERE pid = $1",
+            session.player_id, status
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        resp.add_key("dicestatus");
+        resp.add_str(&status);
+    } else {
+        let mut dice = [0; 5];
+        for (die, val) in dice.iter_mut().zip(row.dice_status.split('/')) {
+            *die = val.parse().map_err(|_| ServerError::Internal)?;
+        }
+        let keep = [args.die1, args.die2, args.die3, args.die4, args.die5];
+        for (die, keep) in dice.iter_mut().zip(keep) {
+            if keep.0 == 0 {
+                *die = rng.i64(1..=6);
+            }
+        }
+
+        // The symbol on the most dice wins. On a tie, the more valuable
+        // (higher) symbol wins
+        let (symbol, count) = (1..=6)
+            .map(|symbol| {
+                (symbol, dice.iter().filter(|a| **a == symbol).count())
+            })
+            .max_by_key(|(symbol, count)| (*count, *symbol))
+            .unwrap_or((1, 0));
+        let symbol =
+            DiceSymbol::from_i64(symbol).ok_or(ServerError::Internal)?;
+        let amount = dice_reward(symbol, count, row.level);
+
+        let (level, experience) = match symbol {
+            DiceSymbol::Experience => {
+                add_experience(row.level, row.experience, amount)
+            }
+            _ => (row.level, row.experience),
+        };
+        let resource = |typ| if symbol == typ { amount } else { 0 };
+        sqlx::query!(
+            "UPDATE character
+             SET silver = silver + $2, stone = stone + $3, wood = wood + $4,
+                 mushrooms = mushrooms + $5, level = $6, experience = $7
+             WHERE pid = $1",
+            session.player_id,
+            resource(DiceSymbol::Silver),
+            resource(DiceSymbol::Stone),
+            resource(DiceSymbol::Wood),
+            resource(DiceSymbol::Mushroom),
+            level,
+            experience,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE tavern SET dice_status = '', quicksand = quicksand + $2
+             WHERE pid = $1",
+            session.player_id,
+            resource(DiceSymbol::Hourglass),
+        )
+        .execute(&mut *tx)
+        .await?;
+        progress_task(&mut tx, session.player_id, TaskTyp::PlayDice).await?;
+
+        resp.add_key("dicestatus");
+        resp.add_str(&dice.map(|a| a.to_string()).join("/"));
+        resp.add_key("dicereward");
+        resp.add_val(symbol as i64);
+        resp.add_val(amount);
+    }
+
+    tx.commit().await?;
+
+    poll(session, "", db, resp).await
+}
+
+/// What the character gets for having the symbol on `count` dice
+fn dice_reward(symbol: DiceSymbol, count: usize, level: i64) -> i64 {
+    let multiplier = [0, 1, 2, 4, 10, 25][count.min(5)];
+    match symbol {
+        DiceSymbol::Silver => (level * 10 + 25) * multiplier,
+        DiceSymbol::Stone => (level + 5) * multiplier,
+        DiceSymbol::Wood => (level * 2 + 10) * multiplier,
+        DiceSymbol::Mushroom | DiceSymbol::Hourglass => multiplier / 4,
+        DiceSymbol::Experience => xp_for_next_level(level) * multiplier / 100,
+    }
+}
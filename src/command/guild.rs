@@ -1,19 +1,21 @@
 use std::fmt::Write;
 
-use sqlx::Sqlite;
+use log::info;
 
-use super::CommandArguments;
-use crate::{request::Session, ResponseBuilder, ServerError, ServerResponse};
+use super::player::HallOfFameArgs;
+use crate::{
+    db::DbPool, request::Session, ResponseBuilder, ServerError, ServerResponse,
+};
 
 pub(crate) async fn group_get_hof(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: HallOfFameArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let rank = args.get_int(0, "rank").unwrap_or_default();
-    let pre = args.get_int(2, "pre").unwrap_or_default();
-    let post = args.get_int(3, "post").unwrap_or_default();
-    let _name = args.get_str(1, "name or rank");
+    let rank = args.rank.unwrap_or_default();
+    let pre = args.pre.unwrap_or_default();
+    let post = args.post.unwrap_or_default();
+    let _name = args.name;
 
     let rank = match rank {
         1.. => rank,
@@ -51,12 +53,12 @@ pub(crate) async fn group_get_hof(
             c.name as leader,
             g.honor,
             (SELECT count(*) AS membercount FROM guild_member as gm WHERE \
-         gm.guild_id = g.id) as `membercount!: i64`,
+         gm.guild_id = g.id) as \"membercount!: i64\",
             g.attacking
             FROM guild as g
             JOIN guild_member as gm on gm.guild_id = g.id
-            NATURAL JOIN character as c
-            WHERE g.world_id = $3 AND RANK = 3
+            JOIN character as c on c.pid = gm.pid
+            WHERE g.world_id = $3 AND gm.rank = 3
             ORDER BY g.honor desc, g.id asc
             LIMIT $2 OFFSET $1",
         offset,
@@ -86,3 +88,81 @@ pub(crate) async fn group_get_hof(
         .add_str(&guilds)
         .build()
 }
+
+/// The honor, that the winner of a guild fight takes from the loser
+const GUILD_FIGHT_HONOR: i64 = 20;
+
+/// Fights out every attack, that a guild has declared. The side, whose
+/// members that signed up for the fight have the higher total level, wins.
+/// The defender wins ties. Every fight is resolved in its own transaction
+pub(crate) async fn resolve_guild_fights(
+    db: &DbPool,
+) -> Result<(), ServerError> {
+    let fights = sqlx::query!(
+        "SELECT id, attacking as \"defender!\" FROM guild
+         WHERE attacking IS NOT NULL"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for fight in &fights {
+        let mut tx = db.begin().await?;
+        let attack = sqlx::query_scalar!(
+            "SELECT COALESCE(SUM(c.level), 0) as \"strength!: i64\"
+             FROM guild_member gm JOIN character c ON c.pid = gm.pid
+             WHERE gm.guild_id = $1 AND gm.is_attacking",
+            fight.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let defense = sqlx::query_scalar!(
+            "SELECT COALESCE(SUM(c.level), 0) as \"strength!: i64\"
+             FROM guild_member gm JOIN character c ON c.pid = gm.pid
+             WHERE gm.guild_id = $1 AND gm.is_defending",
+            fight.defender
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (winner, loser) = match attack > defense {
+            true => (fight.id, fight.defender),
+            false => (fight.defender, fight.id),
+        };
+        sqlx::query!(
+            "UPDATE guild SET honor = honor + $2 WHERE id = $1", winner,
+            GUILD_FIGHT_HONOR
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE guild
+             SET honor = CASE WHEN honor > $2 THEN honor - $2 ELSE 0 END
+             WHERE id = $1",
+            loser,
+            GUILD_FIGHT_HONOR
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE guild SET attacking = NULL WHERE id = $1", fight.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE guild_member SET is_attacking = FALSE WHERE guild_id = $1",
+            fight.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE guild_member SET is_defending = FALSE WHERE guild_id = $1",
+            fight.defender
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+    info!("Resolved {} guild fights", fights.len());
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use num_traits::FromPrimitive;
+use sqlx::Transaction;
+
+use super::{
+    item::{main_class, RawItemTyp, EQUIPMENT_SLOTS},
+    shop::{buy_item, MAGIC_SHOP, WEAPON_SHOP},
+    update::poll,
+    InRange, ServerError, ServerResponse,
+};
+use crate::{
+    db::{Backend, DbPool},
+    request::Session,
+};
+
+/// Shops buy items back for their price divided by this
+const SELL_DIVISOR: i64 = 4;
+
+/// A place in the equipment, or the bag of a character, that can hold an
+/// item. The positions are 0 based
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Equipment(usize),
+    Bag(usize),
+}
+
+impl Slot {
+    /// Parses the (1 based) position in one of the inventories, that the
+    /// client sends
+    fn parse(inventory: i64, pos: i64) -> Result<Slot, ServerError> {
+        let idx = (pos - 1) as usize;
+        match inventory {
+            1 if (1..=10).contains(&pos) => Ok(Slot::Equipment(idx)),
+            2 if (1..=5).contains(&pos) => Ok(Slot::Bag(idx)),
+            _ => Err(ServerError::BadRequest),
+        }
+    }
+
+    /// The table and column, that store the id of the item in this slot
+    fn column(self) -> (&'static str, String) {
+        match self {
+            Slot::Equipment(idx) => ("equipment", EQUIPMENT_SLOTS[idx].into()),
+            Slot::Bag(idx) => ("bag", format!("pos{}", idx + 1)),
+        }
+    }
+}
+
+command_args! {
+    pub(crate) struct ItemMoveArgs {
+        /// 1 for the equipment, 2 for the bag and 3 and 4 to buy from the
+        /// weapon, or magic shop
+        from: InRange<1, 4>,
+        from_pos: InRange<1, 10>,
+        /// Like `from`, but moving to a shop sells the item and 0 destroys
+        /// it
+        to: InRange<0, 4>,
+        to_pos: InRange<0, 10>,
+    }
+}
+
+/// Moves an item between the equipment, the bag and the shops. If there
+/// already is an item at the target, both items swap places. Bought items
+/// need a free target and selling pays a quarter of the price
+pub(crate) async fn player_item_move(
+    session: Session,
+    db: &DbPool,
+    args: ItemMoveArgs,
+) -> Result<ServerResponse, ServerError> {
+    let pid = session.player_id;
+    let mut tx = db.begin().await?;
+
+    let (item, from) = match args.from.0 {
+        shop @ (WEAPON_SHOP | MAGIC_SHOP) => {
+            (buy_item(&mut tx, pid, shop, args.from_pos.0).await?, None)
+        }
+        from => {
+            let from = Slot::parse(from, args.from_pos.0)?;
+            let item = slot_item(&mut tx, pid, from)
+                .await?
+                .ok_or(ServerError::BadRequest)?;
+            (item, Some(from))
+        }
+    };
+
+    match args.to.0 {
+        // Wares can only be bought into a slot. Destroying them, or selling
+        // them right back, is never what the client wants
+        0 | WEAPON_SHOP | MAGIC_SHOP if from.is_none() => {
+            return Err(ServerError::BadRequest);
+        }
+        0 => {
+            // Removing the item also clears its slot
+            sqlx::query!("DELETE FROM item WHERE id = $1", item)
+                .execute(&mut *tx)
+                .await?;
+        }
+        WEAPON_SHOP | MAGIC_SHOP => {
+            let price = sqlx::query_scalar!(
+                "SELECT silver FROM item WHERE id = $1", item
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query!("DELETE FROM item WHERE id = $1", item)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query!(
+                "UPDATE character SET silver = silver + $2 WHERE pid = $1",
+                pid,
+                price / SELL_DIVISOR
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        to => {
+            let to = Slot::parse(to, args.to_pos.0)?;
+            let other = slot_item(&mut tx, pid, to).await?;
+
+            // The items swap places, so both have to fit, where they end up
+            check_fits(&mut tx, pid, item, to).await?;
+            match from {
+                None if other.is_some() => {
+                    return Err(ServerError::InventoryFull);
+                }
+                None => {}
+                Some(from) => {
+                    if let Some(other) = other {
+                        check_fits(&mut tx, pid, other, from).await?;
+                    }
+                    if to != from {
+                        set_slot_item(&mut tx, pid, from, other).await?;
+                    }
+                }
+            }
+            set_slot_item(&mut tx, pid, to, Some(item)).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
+
+async fn slot_item(
+    tx: &mut Transaction<'_, Backend>,
+    pid: i64,
+    slot: Slot,
+) -> Result<Option<i64>, ServerError> {
+    let (table, column) = slot.column();
+    Ok(sqlx::query_scalar(&format!(
+        "SELECT {column} FROM {table} WHERE pid = $1"
+    ))
+    .bind(pid)
+    .fetch_one(&mut **tx)
+    .await?)
+}
+
+async fn set_slot_item(
+    tx: &mut Transaction<'_, Backend>,
+    pid: i64,
+    slot: Slot,
+    item: Option<i64>,
+) -> Result<(), ServerError> {
+    let (table, column) = slot.column();
+    sqlx::query(&format!("UPDATE {table} SET {column} = $1 WHERE pid = $2"))
+        .bind(item)
+        .bind(pid)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Makes sure the character can put the item into the slot. The bag takes
+/// everything, but equipment slots only take items of their type, that are
+/// made for the class of the character. Jewelry can be worn by everyone
+async fn check_fits(
+    tx: &mut Transaction<'_, Backend>,
+    pid: i64,
+    item: i64,
+    slot: Slot,
+) -> Result<(), ServerError> {
+    let Slot::Equipment(idx) = slot else {
+        return Ok(());
+    };
+    let row = sqlx::query!(
+        "SELECT item.item_type, item.class, character.class as char_class
+         FROM item, character
+         WHERE item.id = $1 AND character.pid = $2",
+        item,
+        pid
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let typ = RawItemTyp::from_i64(row.item_type);
+    let jewelry = matches!(
+        typ,
+        Some(RawItemTyp::Amulet | RawItemTyp::Ring | RawItemTyp::Talisman)
+    );
+    let (class, _) = main_class(row.char_class);
+    if typ.and_then(RawItemTyp::equipment_slot) != Some(idx)
+        || (!jewelry && row.class != class as i64)
+    {
+        return Err(ServerError::BadRequest);
+    }
+    Ok(())
+}
@@ -1,8 +1,16 @@
+use fastrand::Rng;
+use log::warn;
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
-use sf_api::gamestate::items::Enchantment;
+use sf_api::gamestate::{character::Class, items::Enchantment};
+use sqlx::Transaction;
 
-use super::ResponseBuilder;
+use crate::{
+    db::{Backend, DbPool},
+    response::ItemData,
+    ServerError,
+};
 
 #[derive(Debug, FromPrimitive, Clone, Copy, Serialize, Deserialize)]
 pub enum RawItemTyp {
@@ -27,6 +35,26 @@ pub enum RawItemTyp {
     Mannequin,
 }
 
+impl RawItemTyp {
+    /// The index of the equipment slot (in `EQUIPMENT_SLOTS`), that items of
+    /// this type can be equipped in
+    pub fn equipment_slot(self) -> Option<usize> {
+        Some(match self {
+            RawItemTyp::Hat => 0,
+            RawItemTyp::BreastPlate => 1,
+            RawItemTyp::Gloves => 2,
+            RawItemTyp::FootWear => 3,
+            RawItemTyp::Amulet => 4,
+            RawItemTyp::Belt => 5,
+            RawItemTyp::Ring => 6,
+            RawItemTyp::Talisman => 7,
+            RawItemTyp::Weapon => 8,
+            RawItemTyp::Shield => 9,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug, FromPrimitive, Clone, Copy, Serialize, Deserialize)]
 pub enum SubItemTyp {
     DungeonKey1 = 1,
@@ -126,14 +154,14 @@ pub enum AtrTyp {
     LightningDamage,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MainClass {
     Warrior = 0,
     Mage = 1,
     Scout = 2,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawItem {
     item_typ: RawItemTyp,
     enchantment: Option<Enchantment>,
@@ -153,63 +181,556 @@ pub struct RawItem {
     gem_pwr: i32,
 }
 
-pub fn add_debug_item(resp: &mut ResponseBuilder, name: impl AsRef<str>) {
-    let path = format!("items/{}.json", name.as_ref());
-    let Some(item) = std::fs::read_to_string(&path)
-        .ok()
-        .and_then(|a| serde_json::from_str::<RawItem>(&a).ok())
-    else {
-        for _ in 0..12 {
-            resp.add_val(0);
+/// The value of `gem_val` for an item with a socket, that has no gem in it
+const EMPTY_SOCKET: i64 = 1;
+
+/// How rare (and how strong) an item is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rarity {
+    Normal,
+    Epic,
+    Legendary,
+}
+
+impl Rarity {
+    /// Rolls the rarity of a randomly found item
+    pub fn roll(rng: &mut Rng) -> Rarity {
+        match rng.u32(..1000) {
+            0..=9 => Rarity::Legendary,
+            10..=99 => Rarity::Epic,
+            _ => Rarity::Normal,
+        }
+    }
+
+    /// How much stronger (and pricier) the item is than a normal one in
+    /// quarters
+    fn quarters(self) -> i64 {
+        match self {
+            Rarity::Normal => 4,
+            Rarity::Epic => 5,
+            Rarity::Legendary => 6,
+        }
+    }
+}
+
+/// The item class of a (1 based) character class and the attribute, that
+/// its items boost
+pub(crate) fn main_class(class: i64) -> (MainClass, AtrTyp) {
+    match Class::from_i64(class.saturating_sub(1)) {
+        Some(Class::Mage | Class::Druid | Class::Bard | Class::Necromancer) => {
+            (MainClass::Mage, AtrTyp::Intelligence)
+        }
+        Some(Class::Scout | Class::Assassin | Class::DemonHunter) => {
+            (MainClass::Scout, AtrTyp::Dexterity)
+        }
+        _ => (MainClass::Warrior, AtrTyp::Strength),
+    }
+}
+
+/// The kinds of equipment, that a character of the item class can wear
+pub(crate) fn equipment_types(class: MainClass) -> Vec<RawItemTyp> {
+    let mut types = vec![
+        RawItemTyp::Weapon,
+        RawItemTyp::BreastPlate,
+        RawItemTyp::FootWear,
+        RawItemTyp::Gloves,
+        RawItemTyp::Hat,
+        RawItemTyp::Belt,
+        RawItemTyp::Amulet,
+        RawItemTyp::Ring,
+        RawItemTyp::Talisman,
+    ];
+    if class == MainClass::Warrior {
+        types.push(RawItemTyp::Shield);
+    }
+    types
+}
+
+/// Generates a random piece of equipment with a random rarity for a
+/// character of the given level and (1 based) class
+pub(crate) fn random_equipment(
+    rng: &mut Rng,
+    level: i64,
+    class: i64,
+) -> RawItem {
+    let types = equipment_types(main_class(class).0);
+    let typ = types[rng.usize(..types.len())];
+    let rarity = Rarity::roll(rng);
+    generate_item(rng, level, class, typ, rarity)
+}
+
+/// Generates an item of the given type for a character of the given level
+/// and (1 based) class. All randomness comes from `rng`, so the same seed
+/// always generates the same item
+pub(crate) fn generate_item(
+    rng: &mut Rng,
+    level: i64,
+    class: i64,
+    typ: RawItemTyp,
+    rarity: Rarity,
+) -> RawItem {
+    let level = level.max(1);
+    let (item_class, main_attribute) = main_class(class);
+    let quarters = rarity.quarters();
+    let scaled = |val: i64| val * quarters / 4;
+
+    let (effect_1, effect_2) = match typ {
+        RawItemTyp::Weapon => (scaled(level * 2), scaled(level * 3)),
+        RawItemTyp::Shield if rarity == Rarity::Normal => (rng.i64(10..=25), 0),
+        RawItemTyp::Shield => (25, 0),
+        RawItemTyp::BreastPlate
+        | RawItemTyp::FootWear
+        | RawItemTyp::Gloves
+        | RawItemTyp::Hat
+        | RawItemTyp::Belt => {
+            // Warriors can make the most use of armor, mages the least
+            let armor = match item_class {
+                MainClass::Warrior => 3,
+                MainClass::Scout => 2,
+                MainClass::Mage => 1,
+            };
+            (scaled(level * armor), 0)
         }
-        return;
+        _ => (0, 0),
     };
 
-    let mut ident = item.item_typ as i64;
-    ident |= item.enchantment.map(|a| a as i64).unwrap_or_default() << 24;
-    ident |= item.gem_val << 16;
-    resp.add_val(ident);
-
-    let mut sub_ident = item.sub_ident.map(|a| a as i64).unwrap_or_default();
-    sub_ident |= item.class.map(|a| a as i64 * 1000).unwrap_or_default();
-    sub_ident |= item.modelid as i64;
-    resp.add_val(sub_ident);
-
-    resp.add_val(item.effect_1 as i64);
-    resp.add_val(item.effect_2 as i64);
-
-    match &item.atrs {
-        AtrEffect::Simple(atrs) => {
-            for atr in atrs {
-                match atr {
-                    Some(x) => resp.add_val(x.atr_typ as i64),
-                    None => resp.add_val(0),
-                };
-            }
-            for atr in atrs {
-                match atr {
-                    Some(x) => resp.add_val(x.atr_val),
-                    None => resp.add_val(0),
-                };
-            }
+    let atr = |atr_typ, atr_val| Some(AtrTuple { atr_typ, atr_val });
+    let value = scaled(level + rng.i64(1..=level / 2 + 1));
+    let atrs = match rarity {
+        Rarity::Normal => [
+            atr(main_attribute, value),
+            if rng.bool() {
+                atr(AtrTyp::Constitution, value / 2)
+            } else {
+                None
+            },
+            None,
+        ],
+        Rarity::Epic => [
+            atr(main_attribute, value),
+            atr(AtrTyp::Constitution, value),
+            atr(AtrTyp::Luck, value),
+        ],
+        Rarity::Legendary => [atr(AtrTyp::All, value), None, None],
+    };
+
+    let enchanted = match rarity {
+        Rarity::Normal => false,
+        Rarity::Epic => rng.u32(..4) == 0,
+        Rarity::Legendary => true,
+    };
+    let enchantment = match typ {
+        RawItemTyp::Weapon => Some(Enchantment::SwordOfVengeance),
+        RawItemTyp::BreastPlate => Some(Enchantment::MariosBeard),
+        RawItemTyp::FootWear => Some(Enchantment::ManyFeetBoots),
+        RawItemTyp::Gloves => Some(Enchantment::ShadowOfTheCowboy),
+        RawItemTyp::Hat => Some(Enchantment::AdventurersArchaeologicalAura),
+        RawItemTyp::Belt => Some(Enchantment::ThirstyWanderer),
+        RawItemTyp::Amulet => Some(Enchantment::UnholyAcquisitiveness),
+        RawItemTyp::Ring => Some(Enchantment::TheGraveRobbersPrayer),
+        RawItemTyp::Talisman => Some(Enchantment::RobberBaronRitual),
+        _ => None,
+    }
+    .filter(|_| enchanted);
+
+    // Legendary items come with a legendary gem, the others sometimes have
+    // an empty socket
+    let (gem_val, gem_pwr) = match rarity {
+        Rarity::Legendary => (GemValue::Legendary as i64, level),
+        Rarity::Epic if rng.u32(..5) == 0 => (EMPTY_SOCKET, 0),
+        Rarity::Normal if rng.u32(..20) == 0 => (EMPTY_SOCKET, 0),
+        _ => (0, 0),
+    };
+
+    // Epic items use the model ids from 50 upwards
+    let modelid = match rarity {
+        Rarity::Normal => rng.i32(1..=10),
+        Rarity::Epic | Rarity::Legendary => rng.i32(50..=60),
+    };
+
+    RawItem {
+        item_typ: typ,
+        enchantment,
+        gem_val,
+        sub_ident: None,
+        class: Some(item_class),
+        modelid,
+        effect_1: effect_1 as i32,
+        effect_2: effect_2 as i32,
+        atrs: AtrEffect::Simple(atrs),
+        silver: scaled(level * 25) as i32,
+        // Shops want mushrooms on top of the silver for the rarer items
+        mushrooms: match rarity {
+            Rarity::Normal => 0,
+            Rarity::Epic => 5,
+            Rarity::Legendary => 15,
+        },
+        gem_pwr: gem_pwr as i32,
+    }
+}
+
+/// The columns of the `equipment` table in the order, that the client
+/// expects the equipped items in. These are also the names of the files,
+/// that `import_items` reads
+pub(crate) const EQUIPMENT_SLOTS: [&str; 10] = [
+    "hat", "breastplate", "gloves", "footwear", "amulet", "belt", "ring",
+    "talisman", "weapon", "shield",
+];
+
+/// An item, as it is stored in the `item` table
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub(crate) struct StoredItem {
+    pub id: i64,
+    pub enchantment: i64,
+    pub item_type: i64,
+    pub effect1: i64,
+    pub effect2: i64,
+    pub ident: i64,
+    pub count: i64,
+    pub expires: Option<i64>,
+    pub gem_type: i64,
+    pub gem_power: i64,
+    pub class: i64,
+    pub atr_typ1: i64,
+    pub atr_val1: i64,
+    pub atr_typ2: i64,
+    pub atr_val2: i64,
+    pub atr_typ3: i64,
+    pub atr_val3: i64,
+    pub model_id: i64,
+    pub silver: i64,
+    pub mushrooms: i64,
+}
+
+impl StoredItem {
+    /// Packs the item into the values, that the client expects
+    pub fn item_data(&self) -> ItemData {
+        let mut res = [0; ItemData::LEN];
+
+        res[0] =
+            self.item_type | (self.enchantment << 24) | (self.gem_type << 16);
+        res[1] = self.ident + self.class * 1000 + self.model_id;
+
+        res[2] = self.effect1;
+        res[3] = self.effect2;
+
+        res[4..7]
+            .copy_from_slice(&[self.atr_typ1, self.atr_typ2, self.atr_typ3]);
+        res[7..10]
+            .copy_from_slice(&[self.atr_val1, self.atr_val2, self.atr_val3]);
+        // Items, that expire, or stack, have no attributes
+        if let Some(expires) = self.expires {
+            res[4] = expires;
         }
-        AtrEffect::Amount(amount) => {
-            for _ in 0..3 {
-                resp.add_val(0);
-            }
-            resp.add_val(amount);
-            for _ in 0..2 {
-                resp.add_val(0);
-            }
+        if self.count > 0 {
+            res[7] = self.count;
         }
-        AtrEffect::Expires(expires) => {
-            resp.add_val(expires);
-            for _ in 0..5 {
-                resp.add_val(0);
+
+        res[10] = self.silver;
+        res[11] = self.mushrooms | (self.gem_power << 16);
+        ItemData(res)
+    }
+}
+
+/// Loads the items with the given ids. Missing items (and `None`) are
+/// returned as empty slots
+pub(crate) async fn load_items<'c, const N: usize>(
+    db: impl sqlx::Executor<'c, Database = Backend>,
+    ids: [Option<i64>; N],
+) -> Result<[ItemData; N], ServerError> {
+    let present: Vec<i64> = ids.iter().flatten().copied().collect();
+    if present.is_empty() {
+        return Ok([ItemData::default(); N]);
+    }
+
+    let params: Vec<_> = (1..=present.len()).map(|i| format!("${i}")).collect();
+    let sql = format!("SELECT * FROM item WHERE id IN ({})", params.join(", "));
+    let mut query = sqlx::query_as::<_, StoredItem>(&sql);
+    for id in present {
+        query = query.bind(id);
+    }
+    let items = query.fetch_all(db).await?;
+
+    Ok(ids.map(|id| {
+        items
+            .iter()
+            .find(|item| Some(item.id) == id)
+            .map(StoredItem::item_data)
+            .unwrap_or_default()
+    }))
+}
+
+/// The items, that the character has equipped
+pub(crate) async fn equipment_items(
+    db: &DbPool,
+    pid: i64,
+) -> Result<[ItemData; 10], ServerError> {
+    let e = sqlx::query!(
+        "SELECT hat, breastplate, gloves, footwear, amulet, belt, ring,
+                talisman, weapon, shield
+         FROM equipment WHERE pid = $1",
+        pid
+    )
+    .fetch_one(db)
+    .await?;
+    load_items(
+        db,
+        [
+            e.hat, e.breastplate, e.gloves, e.footwear, e.amulet, e.belt,
+            e.ring, e.talisman, e.weapon, e.shield,
+        ],
+    )
+    .await
+}
+
+/// The (min, max) damage of the weapon the character has equipped.
+/// Characters without a weapon fight with their fists
+pub(crate) async fn weapon_damage<'c>(
+    db: impl sqlx::Executor<'c, Database = Backend>,
+    pid: i64,
+) -> Result<(u32, u32), ServerError> {
+    let weapon = sqlx::query!(
+        "SELECT item.effect1, item.effect2
+         FROM equipment JOIN item ON item.id = equipment.weapon
+         WHERE equipment.pid = $1",
+        pid
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(match weapon {
+        Some(w) => (w.effect1.max(1) as u32, w.effect2.max(1) as u32),
+        None => (1, 2),
+    })
+}
+
+/// Adds a single attribute effect of an item to the attribute bonus
+/// (strength, dexterity, intelligence, constitution, luck). Effects, that do
+/// not change attributes are ignored
+fn add_attribute_effect(bonus: &mut [i64; 5], atr_typ: i64, atr_val: i64) {
+    match atr_typ {
+        1..=5 => bonus[atr_typ as usize - 1] += atr_val,
+        6 => bonus.iter_mut().for_each(|a| *a += atr_val),
+        // The main attribute of the class, constitution and luck
+        21..=23 => {
+            for idx in [atr_typ as usize - 21, 3, 4] {
+                bonus[idx] += atr_val;
             }
         }
+        _ => {}
+    }
+}
+
+/// The sum of the attributes of all items, that the character has equipped
+pub(crate) async fn equipment_bonus<'c>(
+    db: impl sqlx::Executor<'c, Database = Backend>,
+    pid: i64,
+) -> Result<[i64; 5], ServerError> {
+    let items = sqlx::query!(
+        "SELECT item.atr_typ1, item.atr_val1, item.atr_typ2, item.atr_val2,
+                item.atr_typ3, item.atr_val3
+         FROM equipment JOIN item ON item.id IN (
+            equipment.hat, equipment.breastplate, equipment.gloves,
+            equipment.footwear, equipment.amulet, equipment.belt,
+            equipment.ring, equipment.talisman, equipment.weapon,
+            equipment.shield
+         )
+         WHERE equipment.pid = $1",
+        pid
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut bonus = [0; 5];
+    for item in items {
+        add_attribute_effect(&mut bonus, item.atr_typ1, item.atr_val1);
+        add_attribute_effect(&mut bonus, item.atr_typ2, item.atr_val2);
+        add_attribute_effect(&mut bonus, item.atr_typ3, item.atr_val3);
+    }
+    Ok(bonus)
+}
+
+/// The items in the bag of the character
+pub(crate) async fn bag_items(
+    db: &DbPool,
+    pid: i64,
+) -> Result<[ItemData; 5], ServerError> {
+    let bag = sqlx::query!(
+        "SELECT pos1, pos2, pos3, pos4, pos5 FROM bag WHERE pid = $1", pid
+    )
+    .fetch_one(db)
+    .await?;
+    load_items(db, [bag.pos1, bag.pos2, bag.pos3, bag.pos4, bag.pos5]).await
+}
+
+/// Replaces the equipment and bag of the character with the items in
+/// `items/{slot}.json` and `items/inventory{pos}.json`. Slots without a file
+/// are left untouched. Returns the amount of imported items
+pub(crate) async fn import_items(
+    db: &DbPool,
+    pid: i64,
+) -> Result<u64, ServerError> {
+    let mut tx = db.begin().await?;
+    let mut imported = 0;
+
+    let slots = EQUIPMENT_SLOTS
+        .iter()
+        .map(|slot| ("equipment", slot.to_string(), slot.to_string()))
+        .chain(
+            (1..=5)
+                .map(|i| ("bag", format!("pos{i}"), format!("inventory{i}"))),
+        );
+    for (table, column, file) in slots {
+        let Some(id) = import_item(&mut tx, &file).await? else {
+            continue;
+        };
+        sqlx::query(&format!(
+            "DELETE FROM item
+             WHERE id = (SELECT {column} FROM {table} WHERE pid = $1)"
+        ))
+        .bind(pid)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!(
+            "UPDATE {table} SET {column} = $1 WHERE pid = $2"
+        ))
+        .bind(id)
+        .bind(pid)
+        .execute(&mut *tx)
+        .await?;
+        imported += 1;
+    }
+
+    tx.commit().await?;
+    Ok(imported)
+}
+
+/// Stores the item in `items/{name}.json` in the item table
+async fn import_item(
+    tx: &mut Transaction<'_, Backend>,
+    name: &str,
+) -> Result<Option<i64>, ServerError> {
+    let path = format!("items/{name}.json");
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let item = match serde_json::from_str::<RawItem>(&content) {
+        Ok(item) => item,
+        Err(e) => {
+            warn!("Could not import {path}: {e}");
+            return Ok(None);
+        }
+    };
+
+    insert_item(tx, &item).await.map(Some)
+}
+
+/// Stores the item in the item table and returns the id of the new row
+pub(crate) async fn insert_item(
+    tx: &mut Transaction<'_, Backend>,
+    item: &RawItem,
+) -> Result<i64, ServerError> {
+    let (atrs, count, expires) = match item.atrs {
+        AtrEffect::Simple(atrs) => (atrs, 0, None),
+        AtrEffect::Amount(amount) => ([None; 3], amount, None),
+        AtrEffect::Expires(expires) => ([None; 3], 0, Some(expires)),
+    };
+    let [atr1, atr2, atr3] = atrs
+        .map(|a| a.map(|a| (a.atr_typ as i64, a.atr_val)).unwrap_or_default());
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO item (item_type, enchantment, gem_type, ident, class, \
+         model_id, effect1, effect2, count, expires, atr_typ1, atr_val1, \
+         atr_typ2, atr_val2, atr_typ3, atr_val3, silver, mushrooms, gem_power)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, \
+         $15, $16, $17, $18, $19) RETURNING id",
+        item.item_typ as i64,
+        item.enchantment.map(|a| a as i64).unwrap_or_default(),
+        item.gem_val,
+        item.sub_ident.map(|a| a as i64).unwrap_or_default(),
+        item.class.map(|a| a as i64).unwrap_or_default(),
+        item.modelid as i64,
+        item.effect_1 as i64,
+        item.effect_2 as i64,
+        count,
+        expires,
+        atr1.0,
+        atr1.1,
+        atr2.0,
+        atr2.1,
+        atr3.0,
+        atr3.1,
+        item.silver as i64,
+        item.mushrooms as i64,
+        item.gem_pwr as i64,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_effects() {
+        let mut bonus = [0; 5];
+        add_attribute_effect(&mut bonus, AtrTyp::Dexterity as i64, 10);
+        assert_eq!(bonus, [0, 10, 0, 0, 0]);
+        add_attribute_effect(&mut bonus, AtrTyp::All as i64, 5);
+        assert_eq!(bonus, [5, 15, 5, 5, 5]);
+        add_attribute_effect(
+            &mut bonus,
+            AtrTyp::IntelligenceConstitutionLuck as i64,
+            2,
+        );
+        assert_eq!(bonus, [5, 15, 7, 7, 7]);
+        // Empty slots and effects, that are no attributes
+        add_attribute_effect(&mut bonus, 0, 0);
+        add_attribute_effect(&mut bonus, AtrTyp::QuestXP as i64, 50);
+        assert_eq!(bonus, [5, 15, 7, 7, 7]);
     }
 
-    resp.add_val(item.silver as i64);
-    resp.add_val(item.mushrooms as i64 | (item.gem_pwr as i64) << 16);
+    #[test]
+    fn generator_is_seedable() {
+        let item = |seed| {
+            let item = random_equipment(&mut Rng::with_seed(seed), 20, 2);
+            serde_json::to_string(&item).unwrap()
+        };
+        assert_eq!(item(42), item(42));
+        assert!((0..10).any(|seed| item(seed) != item(42)));
+    }
+
+    #[test]
+    fn generates_class_appropriate_items() {
+        let mut rng = Rng::with_seed(1);
+        for _ in 0..1000 {
+            // Mages never get shields and their items boost intelligence
+            let item = random_equipment(&mut rng, 30, 2);
+            assert!(!matches!(item.item_typ, RawItemTyp::Shield));
+            assert_eq!(item.class, Some(MainClass::Mage));
+            let AtrEffect::Simple([Some(main), ..]) = item.atrs else {
+                panic!("item without attributes: {item:?}");
+            };
+            assert!(matches!(main.atr_typ, AtrTyp::Intelligence | AtrTyp::All));
+            assert!(main.atr_val > 30, "{item:?}");
+        }
+    }
+
+    #[test]
+    fn rarer_items_are_stronger() {
+        let weapon = |rarity| {
+            let mut rng = Rng::with_seed(3);
+            generate_item(&mut rng, 50, 1, RawItemTyp::Weapon, rarity)
+        };
+        let normal = weapon(Rarity::Normal);
+        let epic = weapon(Rarity::Epic);
+        let legendary = weapon(Rarity::Legendary);
+        assert!(normal.effect_2 < epic.effect_2);
+        assert!(epic.effect_2 < legendary.effect_2);
+        assert!(normal.silver < legendary.silver);
+        assert!(normal.enchantment.is_none());
+        assert!(legendary.enchantment.is_some());
+        assert_eq!(legendary.gem_val, GemValue::Legendary as i64);
+    }
 }
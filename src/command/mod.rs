@@ -1,50 +1,157 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use account::{account_check, account_create, account_delete, account_login};
+use account::*;
+pub(crate) use args::{ArgValue, FromArgs, InRange};
+use attributes::player_attribute_increase;
+use dice::roll_dice;
 use guild::group_get_hof;
+pub(crate) use guild::resolve_guild_fights;
+use inventory::player_item_move;
 use log::{debug, error, warn};
 use player::*;
-use sqlx::Sqlite;
+pub use quest::repair_quests;
+pub(crate) use quest::reroll_quests;
+use quest::{generate_quest, insert_quest, quest_monster};
+use shop::player_new_wares;
+pub(crate) use shop::{reroll_shop, MAGIC_SHOP, WEAPON_SHOP};
+pub(crate) use task::roll_daily_tasks;
+use task::{progress_task, TaskTyp};
 use update::poll;
+use work::{player_work_cancel, player_work_finished, player_work_start};
 
-use crate::{request::Session, response::*, SERVER_VERSION};
+use crate::{
+    capture::{self, CapturedCommand},
+    config::get_config,
+    db::DbPool,
+    request::Session,
+    response::*,
+    SERVER_VERSION,
+};
 
+#[macro_use]
+mod args;
 mod account;
+mod attributes;
 mod debug;
+mod dice;
 mod guild;
+mod inventory;
 mod item;
 mod player;
+mod quest;
+mod shop;
+mod task;
 mod update;
+mod work;
 
 #[derive(Debug)]
 pub struct CommandArguments<'a>(pub Vec<&'a str>);
 
-impl<'a> CommandArguments<'a> {
-    pub fn get_int(
-        &self,
-        pos: usize,
-        name: &'static str,
-    ) -> Result<i64, ServerError> {
-        self.0
-            .get(pos)
-            .and_then(|a| a.parse().ok())
-            .ok_or_else(|| ServerError::MissingArgument(name))
-    }
+type CommandFuture<'a> = Pin<
+    Box<dyn Future<Output = Result<ServerResponse, ServerError>> + Send + 'a>,
+>;
 
-    pub fn get_str(
-        &self,
-        pos: usize,
-        name: &'static str,
-    ) -> Result<&str, ServerError> {
-        self.0
-            .get(pos)
-            .copied()
-            .ok_or_else(|| ServerError::MissingArgument(name))
-    }
+/// A command the client can send to us
+pub(crate) struct CommandDef {
+    pub name: &'static str,
+    /// Whether or not this command can only be used with a valid session
+    pub needs_auth: bool,
+    run: for<'a> fn(
+        Session,
+        &'a DbPool,
+        CommandArguments<'a>,
+    ) -> CommandFuture<'a>,
+}
+
+/// Builds the command definitions. Every handler takes the session, the db
+/// and its typed arguments (see `command_args!`). Commands marked as
+/// `(public)` can be used without being logged in
+macro_rules! commands {
+    ($($name:literal $(($public:ident))? => $handler:path),* $(,)?) => {
+        &[$(CommandDef {
+            name: $name,
+            needs_auth: !commands!(@public $($public)?),
+            run: |session, db, args| {
+                Box::pin(async move {
+                    $handler(session, db, FromArgs::from_args(&args)?).await
+                })
+            },
+        }),*]
+    };
+    (@public public) => { true };
+    (@public) => { false };
+}
+
+static COMMANDS: &[CommandDef] = commands![
+    "AccountCheck" (public) => account_check,
+    "AccountCreate" (public) => account_create,
+    "AccountDelete" (public) => account_delete,
+    "AccountLogin" (public) => account_login,
+    "AccountSetLanguage" => acknowledge, // TODO:
+    "GroupGetHallOfFame" => group_get_hof,
+    "PendingRewardView" => pending_reward_view,
+    "PlayerAdventureFinished" => player_finish_quest,
+    "PlayerAdventureStart" => player_start_quest,
+    "PlayerAdventureStop" => player_stop_quest,
+    "PlayerArenaEnemy" => player_arena_enemy,
+    "PlayerArenaFight" => player_arena_fight,
+    "PlayerAttributIncrease" => player_attribute_increase,
+    "PlayerBeerBuy" => player_beer_buy,
+    "PlayerLookAt" => player_look_at,
+    "PlayerGambleGold" => player_gamble_gold,
+    "PlayerGetHallOfFame" => player_get_hof,
+    "PlayerHelpshiftAuthtoken" (public) => player_helpshift_auth_token,
+    "PlayerItemMove" => player_item_move,
+    "PlayerMountBuy" => player_mount_buy,
+    "PlayerNewWares" => player_new_wares,
+    "PlayerPollScrapbook" => acknowledge, // TODO:
+    "PlayerSetDescription" => player_set_descr,
+    "PlayerSetFace" => player_set_face,
+    "PlayerTutorialStatus" => player_tutorial,
+    "PlayerWhisper" => player_whisper,
+    "PlayerWorkCancel" => player_work_cancel,
+    "PlayerWorkFinished" => player_work_finished,
+    "PlayerWorkStart" => player_work_start,
+    "Poll" => player_poll,
+    "RollDice" => roll_dice,
+    "UserSettingsUpdate" => acknowledge, // TODO:
+    "getserverversion" (public) => get_server_version,
+];
+
+pub(crate) fn find_command(name: &str) -> Option<&'static CommandDef> {
+    COMMANDS.iter().find(|a| a.name == name)
 }
 
 pub(crate) async fn handle_command<'a>(
-    db: &sqlx::Pool<Sqlite>,
+    db: &DbPool,
+    name: &'a str,
+    args: CommandArguments<'a>,
+    session: Session,
+) -> Result<ServerResponse, ServerError> {
+    if !capture::is_capturing() {
+        return run_command(db, name, args, session).await;
+    }
+
+    let mut captured = CapturedCommand {
+        command: name.to_string(),
+        args: capture::redacted_args(name, &args.0),
+        pid: session.player_id,
+        world_id: session.world_id,
+        seed: capture::seed_rng(),
+        response: String::new(),
+    };
+    let res = run_command(db, name, args, session).await;
+    captured.response = capture::response_body(&res);
+    capture::record(&captured);
+    res
+}
+
+async fn run_command<'a>(
+    db: &DbPool,
     name: &'a str,
     args: CommandArguments<'a>,
     session: Session,
@@ -53,50 +160,48 @@ pub(crate) async fn handle_command<'a>(
         debug!("Received: {name}: {:?}", args);
     }
 
-    if !session.can_request(name) {
-        // TODO: Validate provided session id
+    let Some(command) = find_command(name) else {
+        error!("Unknown command: {name} - {:?}", args);
+        return Err(ServerError::UnknownRequest(name.into()));
+    };
+
+    if command.needs_auth && !session.is_authed() {
         warn!("{name} requires auth");
         Err(ServerError::InvalidAuth)?;
     }
 
-    match name {
-        "AccountCheck" => account_check(db, args).await,
-        "AccountCreate" => account_create(session, db, args).await,
-        "AccountDelete" => account_delete(session, db, args).await,
-        "AccountLogin" => account_login(session, db, args).await,
-        "AccountSetLanguage" => Ok(ServerResponse::Success), // TODO:
-        "GroupGetHallOfFame" => group_get_hof(session, db, args).await,
-        "PendingRewardView" => pending_reward_view(session, db, args).await,
-        "PlayerAdventureFinished" => player_finish_quest(session, db).await,
-        "PlayerAdventureStart" => player_start_quest(session, db, args).await,
-        "PlayerArenaEnemy" => poll(session, "", db, Default::default()).await,
-        "PlayerArenaFight" => player_arena_fight(session, db, args).await,
-        "PlayerLookAt" => player_look_at(session, db, args).await,
-        "PlayerGambleGold" => player_gamble_gold(session, db, args).await,
-        "PlayerGetHallOfFame" => player_get_hof(session, db, args).await,
-        "PlayerHelpshiftAuthtoken" => player_helpshift_auth_token(),
-        "PlayerMountBuy" => player_mount_buy(session, db, args).await,
-        "PlayerPollScrapbook" => Ok(ServerResponse::Success), // TODO:
-        "PlayerSetDescription" => player_set_descr(session, db, args).await,
-        "PlayerSetFace" => player_set_face(session, db, args).await,
-        "PlayerTutorialStatus" => player_tutorial(session, db, args).await,
-        "PlayerWhisper" => player_whisper(session, db, args).await,
-        "Poll" => poll(session, "poll", db, Default::default()).await,
-        "UserSettingsUpdate" => Ok(ServerResponse::Success), // TODO:
-        "getserverversion" => get_server_version(session, db).await,
-        _ => {
-            error!("Unknown command: {name} - {:?}", args);
-            Err(ServerError::UnknownRequest(name.into()))
-        }
+    (command.run)(session, db, args).await
+}
+
+/// For commands, that we accept, but do not do anything with (yet)
+async fn acknowledge(
+    _session: Session,
+    _db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    Ok(ServerResponse::Success)
+}
+
+async fn player_poll(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    poll(session, "poll", db, Default::default()).await
+}
+
+command_args! {
+    pub(crate) struct PendingRewardArgs {
+        msg_id: i64,
     }
 }
 
 async fn pending_reward_view(
     _session: Session,
-    _db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    _db: &DbPool,
+    args: PendingRewardArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let _id = args.get_int(0, "msg_id")?;
+    let _id = args.msg_id;
     let mut resp = ResponseBuilder::default();
     resp.add_key("pendingrewardressources");
     for v in 1..=6 {
@@ -109,7 +214,11 @@ async fn pending_reward_view(
     resp.build()
 }
 
-fn player_helpshift_auth_token() -> Result<ServerResponse, ServerError> {
+async fn player_helpshift_auth_token(
+    _session: Session,
+    _db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
     ResponseBuilder::default()
         .add_key("helpshiftauthtoken")
         .add_val("+eZGNZyCPfOiaufZXr/WpzaaCNHEKMmcT7GRJOGWJAU=")
@@ -118,14 +227,15 @@ fn player_helpshift_auth_token() -> Result<ServerResponse, ServerError> {
 
 async fn get_server_version(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
+    db: &DbPool,
+    _args: (),
 ) -> Result<ServerResponse, ServerError> {
     let res = sqlx::query!(
         "SELECT
                 (SELECT COUNT(*) FROM Character WHERE world_id = $1) as \
-         `charactercount!: i64`,
+         \"charactercount!: i64\",
                     (SELECT COUNT(*) FROM Guild WHERE world_id = $1) as \
-         `guildcount!: i64`
+         \"guildcount!: i64\"
                     ",
         session.world_id
     )
@@ -151,7 +261,7 @@ async fn get_server_version(
         .build()
 }
 
-fn now() -> i64 {
+pub(crate) fn now() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time warp")
@@ -178,6 +288,16 @@ fn get_debug_value_default(name: &str, default: i64) -> i64 {
         .unwrap_or(default)
 }
 
+/// The "One Beer, Two Beer, Free Beer" event, which allows drinking an extra
+/// beer
+pub(crate) const EVENT_BEER: i64 = 0x1000;
+
+/// The flags of the currently active events (`tavernspecialsub`). There is
+/// no event schedule yet, so these are set in the config
+pub(crate) fn active_events() -> i64 {
+    get_config().server.events
+}
+
 pub struct Portrait {
     mouth: i32,
     hair: i32,
@@ -233,6 +353,29 @@ fn effective_mount(mount_end: &mut i64, mount: &mut i64) -> f32 {
     }
 }
 
+/// The length of a quest, once the mount effect has been applied. The same
+/// length is shown to the client and deducted from the thirst
+fn mounted_quest_length(length: i64, mount_effect: f32) -> i64 {
+    (length as f32 * mount_effect) as i64
+}
+
+/// Adds the experience to the character and levels it up, if it has enough.
+/// Returns the new level and experience
+pub(crate) fn add_experience(
+    mut level: i64,
+    experience: i64,
+    gained: i64,
+) -> (i64, i64) {
+    let mut total_xp = experience + gained;
+    let mut required_xp = xp_for_next_level(level);
+    while total_xp > required_xp {
+        level += 1;
+        total_xp -= required_xp;
+        required_xp = xp_for_next_level(level);
+    }
+    (level, total_xp)
+}
+
 pub(crate) fn xp_for_next_level(level: i64) -> i64 {
     static LOOKUP: [i64; 392] = [
         400, 900, 1400, 1800, 2200, 2890, 3580, 4405, 5355, 6435, 7515, 8925,
@@ -1,27 +1,44 @@
 use std::{borrow::BorrowMut, fmt::Write, ptr::{null, null_mut}, vec};
 
 use fastrand::Rng;
-use log::error;
+use log::{error, warn};
 use num_traits::FromPrimitive;
 use enum_map::EnumMap;
 use sf_api::{
-    command::AttributeType, gamestate::character::{Class, Gender, Race}, misc::from_sf_string, simulate::{Battle, BattleFighter, BattleSide, ClassEffect, Element, EquipmentEffects, UpgradeableFighter}
+    command::AttributeType, gamestate::{character::{Class, Gender, Race}, items::Enchantment}, misc::from_sf_string, simulate::{Battle, BattleFighter, BattleSide, ClassEffect, Element, EquipmentEffects, UpgradeableFighter}
 };
-use sqlx::Sqlite;
 
 use super::{
+    active_events, add_experience,
     debug::{handle_cheat_command, CheatCmd},
-    effective_mount, in_seconds, now, poll, xp_for_next_level,
-    CommandArguments, Portrait, ResponseBuilder, ServerError, ServerResponse,
+    effective_mount, in_seconds,
+    item::{equipment_bonus, equipment_items, load_items, weapon_damage},
+    mounted_quest_length, now, poll, progress_task, quest_monster,
+    reroll_quests, xp_for_next_level, InRange, Portrait, ResponseBuilder,
+    ServerError, ServerResponse, TaskTyp, EVENT_BEER,
 };
-use crate::request::Session;
+use crate::{
+    config::get_config,
+    db::DbPool,
+    request::Session,
+    response::{
+        FightHeader, FightKind, FightResult, FighterInfo, FighterLook,
+        ItemData, LookAt, PortraitData,
+    },
+};
+
+command_args! {
+    pub(crate) struct MountBuyArgs {
+        mount: InRange<0, 4>,
+    }
+}
 
 pub(crate) async fn player_mount_buy(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: MountBuyArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let mount = args.get_int(0, "mount")?;
+    let mount = args.mount.0;
     let mut tx = db.begin().await?;
 
     let character = sqlx::query!(
@@ -50,7 +67,7 @@ pub(crate) async fn player_mount_buy(
         _ => 0,
     };
     if mushrooms < mush_price {
-        return Err(ServerError::NotEnoughMoney);
+        return Err(ServerError::NotEnoughMushrooms);
     }
     mushrooms -= mush_price;
 
@@ -83,15 +100,18 @@ pub(crate) async fn player_mount_buy(
     poll(session, "", db, Default::default()).await
 }
 
+command_args! {
+    pub(crate) struct TutorialArgs {
+        status: InRange<0, 0xFFFFFFF>,
+    }
+}
+
 pub(crate) async fn player_tutorial(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: TutorialArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let status = args.get_int(0, "tutorial status")?;
-    if !(0..=0xFFFFFFF).contains(&status) {
-        Err(ServerError::BadRequest)?;
-    }
+    let status = args.status.0;
     sqlx::query!(
         "UPDATE CHARACTER SET tutorial_status = $1 WHERE pid = $2", status,
         session.player_id,
@@ -101,17 +121,28 @@ pub(crate) async fn player_tutorial(
     Ok(ServerResponse::Success)
 }
 
+command_args! {
+    pub(crate) struct WhisperArgs {
+        name: String,
+        message: String,
+    }
+}
+
 pub(crate) async fn player_whisper(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: WhisperArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let name = args.get_str(0, "name")?.to_lowercase();
-    if name != "server" {
-        todo!()
+    if args.name.to_lowercase() != "server" {
+        // Messages between players are not supported yet
+        return Err(ServerError::InvalidArgument("name"));
+    }
+    if !get_config().server.cheats {
+        warn!("{} tried to use a cheat command", session.player_id);
+        return Err(ServerError::BadRequest);
     }
     use clap::Parser;
-    let command = CheatCmd::try_parse_from(args.get_str(1, "args")?.split(' '))
+    let command = CheatCmd::try_parse_from(args.message.split(' '))
         .map_err(|e| {
             error!("Error while parsing command: {:?}", e);
             ServerError::BadRequest
@@ -119,10 +150,20 @@ pub(crate) async fn player_whisper(
     handle_cheat_command(session, db, command).await
 }
 
+command_args! {
+    pub(crate) struct QuestFinishArgs {
+        /// 1 to skip the rest of the quest with a mushroom, 2 to skip it with
+        /// an hourglass
+        skip: Option<InRange<0, 2>>,
+    }
+}
+
 pub(crate) async fn player_finish_quest(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
+    db: &DbPool,
+    args: QuestFinishArgs,
 ) -> Result<ServerResponse, ServerError> {
+    let mut rng = Rng::new();
     let mut tx = db.begin().await?;
 
     let row = sqlx::query!(
@@ -172,15 +213,24 @@ pub(crate) async fn player_finish_quest(
         gender,
         class,
         experience,
-        portrait.influencer
+        character.mushrooms,
+        quicksand,
+        portrait.influencer,
+
+        a.strength,
+        a.dexterity,
+        a.intelligence,
+        a.stamina,
+        a.luck
 
         FROM character
             NATURAL JOIN PORTRAIT
             NATURAL JOIN tavern
             NATURAL JOIN activity
+            JOIN attributes as a on a.id = character.attributes
             JOIN quest as q1 on tavern.quest1 = q1.id
             JOIN quest as q2 on tavern.quest2 = q2.id
-            JOIN quest as q3 on tavern.quest2 = q3.id
+            JOIN quest as q3 on tavern.quest3 = q3.id
             WHERE pid = $1",
         session.player_id,
     )
@@ -194,13 +244,42 @@ pub(crate) async fn player_finish_quest(
     let busyuntil = row.busy_until;
 
     if busyuntil > now() {
-        // Quest is still going
-        return Err(ServerError::StillBusy);
+        // Quest is still going, so the rest of it has to be skipped
+        match args.skip.map(|a| a.0) {
+            Some(1) => {
+                if row.mushrooms < 1 {
+                    return Err(ServerError::NotEnoughMushrooms);
+                }
+                sqlx::query!(
+                    "UPDATE character SET mushrooms = mushrooms - 1
+                     WHERE pid = $1",
+                    session.player_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            Some(2) => {
+                // Every started minute, that is left, costs an hourglass
+                let glasses = (busyuntil - now() + 59) / 60;
+                if row.quicksand < glasses {
+                    return Err(ServerError::NotEnoughHourglasses);
+                }
+                sqlx::query!(
+                    "UPDATE tavern SET quicksand = quicksand - $2
+                     WHERE pid = $1",
+                    session.player_id,
+                    glasses
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            _ => return Err(ServerError::StillBusy),
+        }
     }
 
     let subtyp = row.sub_type;
 
-    let (_item, location, monster, mush, silver, quest_xp) = match subtyp {
+    let (item, location, monster, mush, silver, quest_xp) = match subtyp {
         1 => (
             row.q1item, row.q1location, row.q1monster, row.q1mush,
             row.q1silver, row.q1xp,
@@ -213,132 +292,126 @@ pub(crate) async fn player_finish_quest(
             row.q3item, row.q3location, row.q3monster, row.q3mush,
             row.q3silver, row.q3xp,
         ),
-        _ => todo!(),
+        _ => {
+            error!(
+                "Character {} is on the invalid quest {subtyp}",
+                session.player_id
+            );
+            return Err(ServerError::Internal);
+        }
     };
 
-    let honor_won = 10;
-
     let mut resp = ResponseBuilder::default();
 
-    resp.add_key("fightresult.battlereward");
-    resp.add_val(true as u8);
-    // won
-    resp.add_val(0);
-    resp.add_val(silver);
-    resp.add_val(quest_xp);
-
-    resp.add_val(mush);
-    resp.add_val(honor_won);
-    for _ in 0..15 {
-        resp.add_val(0);
-    }
-
-    resp.add_key("fightheader.fighters");
     let monster_id = -monster;
-    let mut character_lvl = row.level;
-    let starting_character_xp = row.experience;
-
-    let mut total_xp = quest_xp + starting_character_xp;
-    let mut required_xp = xp_for_next_level(character_lvl);
-    // Level up the character
-    while total_xp > required_xp {
-        character_lvl += 1;
-        total_xp -= required_xp;
-        required_xp = xp_for_next_level(character_lvl);
-    }
-
-    let character_attributes = [1, 1, 1, 1, 1];
-    let monster_attributes = [1, 1, 1, 1, 1];
-    let monster_hp = 10_000;
-    let character_hp = 10_000;
-
-    resp.add_val(1);
-    resp.add_val(0);
-    resp.add_val(0);
-
-    // Location
-    resp.add_val(location);
-
-    resp.add_val(1);
-    resp.add_val(session.player_id);
-    resp.add_str(&row.name);
-    resp.add_val(character_lvl);
-    for _ in 0..2 {
-        resp.add_val(character_hp);
-    }
-    for val in character_attributes {
-        resp.add_val(val);
-    }
-
-    resp.add_val(row.mouth);
-    resp.add_val(row.hair);
-    resp.add_val(row.brows);
-    resp.add_val(row.eyes);
-    resp.add_val(row.beards);
-    resp.add_val(row.nose);
-    resp.add_val(row.ears);
-    resp.add_val(row.extra);
-    resp.add_val(row.horns);
-
-    resp.add_val(row.influencer);
-    // special influencer portraits
-
-    resp.add_val(row.race);
-    // race
-    resp.add_val(row.gender);
-    // gender
-    resp.add_val(row.class);
-    // class
-
-    // Main weapon
-    for _ in 0..12 {
-        resp.add_val(0);
-    }
-
-    // Sub weapon
-    for _ in 0..12 {
-        resp.add_val(0);
-    }
-
-    // Monster
-    for _ in 0..2 {
-        resp.add_val(monster_id);
-    }
-    resp.add_val(character_lvl);
-    // monster lvl
-    resp.add_val(monster_hp);
-    resp.add_val(monster_hp);
-    for attr in monster_attributes {
-        resp.add_val(attr);
-    }
-    resp.add_val(monster_id);
-    for _ in 0..11 {
-        resp.add_val(0);
+    let quest_monster = quest_monster(monster, row.level);
+
+    let mut character_attributes = [
+        row.strength, row.dexterity, row.intelligence, row.stamina, row.luck,
+    ];
+    let bonus = equipment_bonus(&mut *tx, session.player_id).await?;
+    for (attribute, bonus) in character_attributes.iter_mut().zip(bonus) {
+        *attribute += bonus;
     }
-    resp.add_val(3);
-    // Class?
+    let weapon = weapon_damage(&mut *tx, session.player_id).await?;
+    let character_fighter =
+        battle_fighter(row.level, row.class, character_attributes, weapon)?;
+    let monster_fighter = battle_fighter(
+        quest_monster.level,
+        quest_monster.class as i64 + 1,
+        quest_monster.attributes,
+        quest_monster.weapon,
+    )?;
+
+    let player = FighterInfo {
+        id: session.player_id,
+        name: row.name,
+        level: row.level,
+        hp: character_fighter.max_hp,
+        attributes: character_attributes,
+        look: FighterLook::Player {
+            portrait: PortraitData {
+                mouth: row.mouth,
+                hair: row.hair,
+                brows: row.brows,
+                eyes: row.eyes,
+                beards: row.beards,
+                nose: row.nose,
+                ears: row.ears,
+                extra: row.extra,
+                horns: row.horns,
+                influencer: row.influencer,
+            },
+            race: row.race,
+            gender: row.gender,
+        },
+        class: row.class,
+        ..Default::default()
+    };
 
-    // Probably also items
+    let mut monster_weapon = ItemData::default();
     // This means just changing the portrait into the character
-    resp.add_val(-1);
-    for _ in 0..23 {
-        resp.add_val(0);
-    }
+    monster_weapon.0[0] = -1;
+
+    let monster = FighterInfo {
+        id: monster_id,
+        name: monster_id.to_string(),
+        level: quest_monster.level,
+        hp: monster_fighter.max_hp,
+        attributes: quest_monster.attributes,
+        look: FighterLook::Monster(monster_id),
+        class: quest_monster.class as i64 + 1,
+        weapon: monster_weapon,
+        ..Default::default()
+    };
 
-    resp.add_key("fight.r");
-    resp.add_str(&format!("{},0,-1000", session.player_id));
+    resp.add_section(&FightHeader {
+        kind: FightKind::Quest,
+        location,
+        fighters: [player, monster],
+    });
 
-    // TODO: actually simulate fight
+    let won = simulate_fight(
+        &mut resp,
+        [session.player_id, monster_id],
+        character_fighter,
+        monster_fighter,
+    );
 
     resp.add_key("winnerid");
-    resp.add_val(session.player_id);
+    resp.add_val(if won { session.player_id } else { monster_id });
 
     resp.add_key("fightversion");
     resp.add_val(1);
 
+    // A lost quest uses up the thirst, but does not reward anything. Like
+    // after a won one, the tavern offers new quests afterwards
+    let honor_won = if won { 10 } else { 0 };
+    let (silver, quest_xp, mush, item) = if won {
+        (silver, quest_xp, mush, item)
+    } else {
+        (0, 0, 0, None)
+    };
+
+    let [item_data] = load_items(&mut *tx, [item]).await?;
+    resp.add_section(&FightResult {
+        won,
+        kind: FightKind::Quest,
+        silver,
+        xp: quest_xp,
+        mushrooms: mush,
+        honor: honor_won,
+        item: item_data,
+        ..Default::default()
+    });
+
+    let (character_lvl, total_xp) =
+        add_experience(row.level, row.experience, quest_xp);
+
     sqlx::query!(
         "UPDATE activity
-                 SET typ = 0, sub_type = 0, started = 0, busy_until = 0
+                 SET typ = 0, sub_type = 0, started = 0, busy_until = 0,
+                     thirst_spent = 0
                  WHERE pid = $1",
         session.player_id,
     )
@@ -360,24 +433,64 @@ pub(crate) async fn player_finish_quest(
     .execute(&mut *tx)
     .await?;
 
-    // TODO: Reroll quests, add item & save fight somewhere for rewatch (save)
+    if let Some(item) = item {
+        let bag = sqlx::query!(
+            "SELECT pos1, pos2, pos3, pos4, pos5 FROM bag WHERE pid = $1",
+            session.player_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let free_slot = [bag.pos1, bag.pos2, bag.pos3, bag.pos4, bag.pos5]
+            .iter()
+            .position(Option::is_none);
+        // If the bag is full, the item is lost alongside the quest
+        if let Some(slot) = free_slot {
+            sqlx::query(&format!(
+                "UPDATE bag SET pos{} = $1 WHERE pid = $2",
+                slot + 1
+            ))
+            .bind(item)
+            .bind(session.player_id)
+            .execute(&mut *tx)
+            .await?;
+            // The item belongs to the character now, so the reroll must not
+            // delete it
+            sqlx::query!("UPDATE quest SET item = NULL WHERE item = $1", item)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    reroll_quests(
+        &mut tx, &mut rng, session.player_id, character_lvl, row.class,
+    )
+    .await?;
+    if won {
+        progress_task(&mut tx, session.player_id, TaskTyp::FinishQuests)
+            .await?;
+    }
+
+    // TODO: Save fight somewhere for rewatch (save)
 
     tx.commit().await?;
 
     poll(session, "", db, resp).await
 }
 
+command_args! {
+    pub(crate) struct QuestStartArgs {
+        quest: InRange<1, 3>,
+        skip_inv: InRange<0, 1>,
+    }
+}
+
 pub(crate) async fn player_start_quest(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: QuestStartArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let quest = args.get_int(0, "quest")?;
-    let skip_inv = args.get_int(1, "skip_inv")?;
-
-    if !(1..=3).contains(&quest) || !(0..=1).contains(&skip_inv) {
-        Err(ServerError::BadRequest)?;
-    }
+    let quest = args.quest.0;
+    let _skip_inv = args.skip_inv;
 
     let mut tx = db.begin().await?;
 
@@ -414,15 +527,12 @@ pub(crate) async fn player_start_quest(
         1 => row.ql1,
         2 => row.ql2,
         _ => row.ql3,
-    } as f32
-        * mount_effect.ceil().max(0.0);
-
-    let quest_length = quest_length as i64;
+    };
+    let quest_length = mounted_quest_length(quest_length, mount_effect);
     let tfa = row.tfa;
 
     if tfa < quest_length {
-        // TODO: Actual error
-        return Err(ServerError::StillBusy);
+        return Err(ServerError::NotEnoughThirst);
     }
     let busy_until = in_seconds(quest_length);
     sqlx::query!(
@@ -430,20 +540,21 @@ pub(crate) async fn player_start_quest(
                     SET typ = 2,
                     sub_type = $2,
                     busy_until = $3,
-                    started = CURRENT_TIMESTAMP
+                    started = $4,
+                    thirst_spent = $5
                 WHERE pid = $1",
         session.player_id,
         quest,
-        busy_until
+        busy_until,
+        now(),
+        quest_length
     )
     .execute(&mut *tx)
     .await?;
 
-    // TODO: We should keep track of how much we deduct here, so that
-    // we can accurately refund this on cancel
     sqlx::query!(
         "UPDATE tavern
-                 SET tfa = max(0, tfa - $2)
+                 SET tfa = CASE WHEN tfa > $2 THEN tfa - $2 ELSE 0 END
                  WHERE pid = $1",
         session.player_id,
         quest_length
@@ -456,13 +567,123 @@ pub(crate) async fn player_start_quest(
     poll(session, "", db, Default::default()).await
 }
 
+pub(crate) async fn player_stop_quest(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    let mut tx = db.begin().await?;
+
+    let activity = sqlx::query!(
+        "SELECT typ, thirst_spent FROM activity WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if activity.typ != 2 {
+        // We are not actually questing
+        return Err(ServerError::BadRequest);
+    }
+
+    sqlx::query!(
+        "UPDATE activity
+                 SET typ = 0, sub_type = 0, started = 0, busy_until = 0,
+                     thirst_spent = 0
+                 WHERE pid = $1",
+        session.player_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE tavern SET tfa = tfa + $2 WHERE pid = $1",
+        session.player_id,
+        activity.thirst_spent
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
+
+/// The thirst for adventure (in seconds) a single beer restores
+const BEER_THIRST: i64 = 20 * 60;
+
+/// How many beers a character can drink per day. A belt enchanted with
+/// Thirsty Wanderer, or the beer event allow a single extra beer. They do not
+/// stack. No guild upgrade changes the limit, so the guild does not matter
+fn beer_limit(thirsty_wanderer: bool, beer_event: bool) -> i64 {
+    10 + (thirsty_wanderer || beer_event) as i64
+}
+
+pub(crate) async fn player_beer_buy(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query!(
+        "SELECT character.mushrooms, beer_drunk,
+                item.enchantment as \"belt?\"
+         FROM character
+         JOIN tavern ON tavern.pid = character.pid
+         JOIN equipment ON equipment.pid = character.pid
+         LEFT JOIN item ON item.id = equipment.belt
+         WHERE character.pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let thirsty_wanderer =
+        row.belt == Some(Enchantment::ThirstyWanderer as i64);
+    let beer_event = active_events() & EVENT_BEER != 0;
+    if row.beer_drunk >= beer_limit(thirsty_wanderer, beer_event) {
+        return Err(ServerError::TooMuchBeer);
+    }
+    if row.mushrooms < 1 {
+        return Err(ServerError::NotEnoughMushrooms);
+    }
+
+    sqlx::query!(
+        "UPDATE character SET mushrooms = mushrooms - 1 WHERE pid = $1",
+        session.player_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE tavern SET beer_drunk = beer_drunk + 1, tfa = tfa + $2
+         WHERE pid = $1",
+        session.player_id,
+        BEER_THIRST
+    )
+    .execute(&mut *tx)
+    .await?;
+    progress_task(&mut tx, session.player_id, TaskTyp::DrinkBeer).await?;
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
+
+command_args! {
+    pub(crate) struct GambleGoldArgs {
+        silver: i64,
+    }
+}
+
 pub(crate) async fn player_gamble_gold(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: GambleGoldArgs,
 ) -> Result<ServerResponse, ServerError> {
     let mut rng = Rng::new();
-    let mut silver = args.get_int(0, "gold value")?;
+    let mut silver = args.silver;
 
     let mut tx = db.begin().await?;
     let character_silver = sqlx::query_scalar!(
@@ -497,20 +718,30 @@ pub(crate) async fn player_gamble_gold(
         .build()
 }
 
+command_args! {
+    /// Used for both the player and the guild Hall of Fame
+    pub(crate) struct HallOfFameArgs {
+        rank: Option<i64>,
+        name: Option<String>,
+        pre: Option<i64>,
+        post: Option<i64>,
+    }
+}
+
 pub(crate) async fn player_get_hof(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: HallOfFameArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let rank = args.get_int(0, "rank").unwrap_or_default();
-    let pre = args.get_int(2, "pre").unwrap_or_default();
-    let post = args.get_int(3, "post").unwrap_or_default();
-    let name = args.get_str(1, "name or rank");
+    let rank = args.rank.unwrap_or_default();
+    let pre = args.pre.unwrap_or_default();
+    let post = args.post.unwrap_or_default();
 
     let rank = match rank {
         1.. => rank,
         _ => {
-            let name = name?;
+            let name =
+                args.name.ok_or(ServerError::MissingArgument("name"))?;
             sqlx::query_scalar!(
                 "WITH selected_character AS
                             (SELECT honor, pid
@@ -528,12 +759,13 @@ pub(crate) async fn player_get_hof(
                                     FROM selected_character)
                                     AND pid <=
                                     (SELECT pid
-                                    FROM selected_character))) AS rank",
+                                    FROM selected_character))) AS \"rank!\"",
                 name,
                 session.world_id
             )
-            .fetch_one(db)
+            .fetch_optional(db)
             .await?
+            .ok_or(ServerError::PlayerNotFound)?
         }
     };
 
@@ -578,13 +810,18 @@ pub(crate) async fn player_get_hof(
         .build()
 }
 
+command_args! {
+    pub(crate) struct SetDescriptionArgs {
+        description: String,
+    }
+}
+
 pub(crate) async fn player_set_descr(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: SetDescriptionArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let description = args.get_str(0, "description")?;
-    let description = from_sf_string(description);
+    let description = from_sf_string(&args.description);
     sqlx::query!(
         "UPDATE character SET description = $1 WHERE pid = $2", description,
         session.player_id
@@ -594,19 +831,26 @@ pub(crate) async fn player_set_descr(
     poll(session, "", db, Default::default()).await
 }
 
+command_args! {
+    pub(crate) struct SetFaceArgs {
+        race: i64,
+        gender: i64,
+        portrait: String,
+    }
+}
+
 pub(crate) async fn player_set_face(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    command_args: CommandArguments<'_>,
+    db: &DbPool,
+    args: SetFaceArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let race = command_args.get_int(0, "race")?;
-    Race::from_i64(race).ok_or_else(|| ServerError::BadRequest)?;
-    let gender = command_args.get_int(1, "gender")?;
+    let race = args.race;
+    Race::from_i64(race).ok_or(ServerError::InvalidArgument("race"))?;
+    let gender = args.gender;
     Gender::from_i64(gender.saturating_sub(1))
-        .ok_or_else(|| ServerError::BadRequest)?;
-    let portrait_str = command_args.get_str(2, "portrait")?;
-    let portrait =
-        Portrait::parse(portrait_str).ok_or_else(|| ServerError::BadRequest)?;
+        .ok_or(ServerError::InvalidArgument("gender"))?;
+    let portrait = Portrait::parse(&args.portrait)
+        .ok_or(ServerError::InvalidArgument("portrait"))?;
 
     let mut tx = db.begin().await?;
     let mushrooms = sqlx::query_scalar!(
@@ -621,7 +865,7 @@ pub(crate) async fn player_set_face(
 
     if mushrooms < 0 {
         tx.rollback().await?;
-        return Err(ServerError::NotEnoughMoney);
+        return Err(ServerError::NotEnoughMushrooms);
     }
 
     sqlx::query!(
@@ -647,20 +891,30 @@ pub(crate) async fn player_set_face(
     Ok(ServerResponse::Success)
 }
 
+command_args! {
+    pub(crate) struct LookAtArgs {
+        /// Either the pid, or the name of the character
+        target: String,
+    }
+}
+
 pub(crate) async fn player_look_at(
-    _session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    session: Session,
+    db: &DbPool,
+    args: LookAtArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let pid = match args.get_int(0, "") {
+    let pid = match args.target.parse() {
         Ok(x) => x,
         Err(_) => {
-            let name = args.get_str(0, "look at pid or name")?;
+            let name = args.target.as_str();
             sqlx::query_scalar!(
-                "SELECT pid FROM character WHERE name = $1", name
+                "SELECT pid FROM character WHERE name = $1 AND world_id = $2",
+                name,
+                session.world_id
             )
-            .fetch_one(db)
+            .fetch_optional(db)
             .await?
+            .ok_or(ServerError::PlayerNotFound)?
         }
     };
 
@@ -675,75 +929,45 @@ pub(crate) async fn player_look_at(
         WHERE pid = $1",
         pid
     )
-    .fetch_one(db)
-    .await?;
+    .fetch_optional(db)
+    .await?
+    .ok_or(ServerError::PlayerNotFound)?;
 
     resp.add_key("otherplayergroupname.r");
     resp.add_val("");
-    resp.add_key("otherplayer.playerlookat");
-    resp.add_val(pid);
-    resp.add_val(0);
-    resp.add_val(info.level);
-    resp.add_val(info.experience); // xp
-    resp.add_val(xp_for_next_level(info.level)); // xp next lvl
-    resp.add_val(info.honor);
-    resp.add_val(10); // TODO: Rank
-    resp.add_val(0); // ?
-    resp.add_val(info.mouth);
-    resp.add_val(info.hair);
-    resp.add_val(info.brows);
-    resp.add_val(info.eyes);
-    resp.add_val(info.beards);
-    resp.add_val(info.nose);
-    resp.add_val(info.ears);
-    resp.add_val(info.extra);
-    resp.add_val(info.horns);
-    resp.add_val(info.influencer);
-    resp.add_val(info.race);
-    resp.add_val(info.gender);
-    resp.add_val(info.class);
-    resp.add_val(info.strength);
-    resp.add_val(info.dexterity);
-    resp.add_val(info.intelligence);
-    resp.add_val(info.stamina);
-    resp.add_val(info.luck);
-    // TODO: Bonus attrs
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(0);
-
-    for _ in 0..8 {
-        resp.add_val(0);
-    }
-
-    // Equipment
-    for _ in 0..10 {
-        for _ in 0..12 {
-            resp.add_val(0);
-        }
-    }
-    resp.add_val(0); // 159 mount
-    resp.add_val(58);
-    resp.add_val(37408);
-    resp.add_val(2723);
-    resp.add_val(11901);
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(1393194397);
-    resp.add_val(1);
-    resp.add_val(4165);
-    resp.add_val(958);
-    resp.add_val(2642);
-    resp.add_val(3906638);
-    for _ in 0..36 {
-        resp.add_val(0);
-    }
-    // Mainly fortress stuff
-    for _ in 0..53 {
-        resp.add_val(0);
-    }
+    resp.add_section(&LookAt {
+        player_id: pid,
+        level: info.level,
+        experience: info.experience,
+        next_level_xp: xp_for_next_level(info.level),
+        honor: info.honor,
+        rank: 10, // TODO: Rank
+        portrait: PortraitData {
+            mouth: info.mouth,
+            hair: info.hair,
+            brows: info.brows,
+            eyes: info.eyes,
+            beards: info.beards,
+            nose: info.nose,
+            ears: info.ears,
+            extra: info.extra,
+            horns: info.horns,
+            influencer: info.influencer,
+        },
+        race: info.race,
+        gender: info.gender,
+        class: info.class,
+        attributes: [
+            info.strength,
+            info.dexterity,
+            info.intelligence,
+            info.stamina,
+            info.luck,
+        ],
+        attribute_bonus: equipment_bonus(db, pid).await?,
+        equipment: equipment_items(db, pid).await?,
+        ..Default::default()
+    });
     resp.add_key("otherdescription.s");
     resp.add_str(&info.description);
     resp.add_key("otherplayername.r");
@@ -769,37 +993,74 @@ pub(crate) async fn player_look_at(
     resp.build()
 }
 
+pub(crate) async fn player_arena_enemy(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    poll(session, "", db, Default::default()).await
+}
+
+/// How long (in seconds) the character has to wait between free arena fights
+const ARENA_COOLDOWN: i64 = 10 * 60;
+
+command_args! {
+    pub(crate) struct ArenaFightArgs {
+        enemy_name: String,
+    }
+}
+
 pub(crate) async fn player_arena_fight(
     session: Session,
-    db: &sqlx::Pool<Sqlite>,
-    args: CommandArguments<'_>,
+    db: &DbPool,
+    args: ArenaFightArgs,
 ) -> Result<ServerResponse, ServerError> {
-    let enemy_name = args.get_str(0, "arena enemy name")?;
+    let enemy_name = args.enemy_name.as_str();
 
     let enemy_id = sqlx::query_scalar!(
-        "SELECT pid FROM character WHERE name = $1", enemy_name
+        "SELECT pid FROM character WHERE name = $1 AND world_id = $2",
+        enemy_name,
+        session.world_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(ServerError::PlayerNotFound)?;
+
+    let mut tx = db.begin().await?;
+    let row = sqlx::query!(
+        "SELECT mushrooms, arena_next_free FROM character WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    // Fighting again before the cooldown is over costs a mushroom
+    let price = (row.arena_next_free > now()) as i64;
+    if row.mushrooms < price {
+        return Err(ServerError::NotEnoughMushrooms);
+    }
+    sqlx::query!(
+        "UPDATE character
+         SET mushrooms = mushrooms - $2, arena_next_free = $3
+         WHERE pid = $1",
+        session.player_id,
+        price,
+        in_seconds(ARENA_COOLDOWN)
     )
-    .fetch_one(db)
+    .execute(&mut *tx)
     .await?;
+    progress_task(&mut tx, session.player_id, TaskTyp::FightInArena).await?;
+    tx.commit().await?;
 
     let mut resp = ResponseBuilder::default();
     resp.add_key("fightversion");
     resp.add_val(2);
 
-    resp.add_key("fightheader.fighters");
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(0);
-    resp.add_val(1);
-
     let fighters = [session.player_id, enemy_id];
 
-    let starting_hp = 10_000;
-
     let mut battle_fighters = Vec::with_capacity(2);
+    let mut fighter_infos: [FighterInfo; 2] = Default::default();
 
-    for pid in fighters {
+    for (pid, fighter_info) in fighters.into_iter().zip(&mut fighter_infos) {
         let fighter = sqlx::query!(
             "SELECT name, portrait.*, a.*, level, class, race, gender
             FROM character c
@@ -811,97 +1072,145 @@ pub(crate) async fn player_arena_fight(
         .fetch_one(db)
         .await?;
 
-        let mut our_attributes: EnumMap<AttributeType, u32> = EnumMap::default();
-        our_attributes[AttributeType::Strength] = fighter.strength as u32;
-        our_attributes[AttributeType::Dexterity] = fighter.dexterity as u32;
-        our_attributes[AttributeType::Intelligence] = fighter.intelligence as u32;
-        our_attributes[AttributeType::Constitution] = fighter.stamina as u32;
-        our_attributes[AttributeType::Luck] = fighter.luck as u32;
-
-        let our_weapon = (100, 200);
-        let our_equip = EquipmentEffects {
-            element_res: EnumMap::default(),
-            element_dmg: EnumMap::default(),
-            weapon: our_weapon,
-            offhand: (0, 0),
-            reaction_boost: false,
-            extra_crit_dmg: false,
-            armor: 0,
-        };
-        let mut our_fighter = BattleFighter {
-            level: fighter.level as u16,
-            is_companion: false,
-            class: Class::from_i64(fighter.class).unwrap(),
-            attributes: our_attributes,
-            max_hp: starting_hp,
-            current_hp: starting_hp,
-            equip: our_equip,
-            rounds_in_battle: 0,
-            class_effect: ClassEffect::Normal,
-            portal_dmg_bonus: 1.0,
+        let mut attributes = [
+            fighter.strength, fighter.dexterity, fighter.intelligence,
+            fighter.stamina, fighter.luck,
+        ];
+        let bonus = equipment_bonus(db, pid).await?;
+        for (attribute, bonus) in attributes.iter_mut().zip(bonus) {
+            *attribute += bonus;
+        }
+        let weapon = weapon_damage(db, pid).await?;
+        let our_fighter =
+            battle_fighter(fighter.level, fighter.class, attributes, weapon)?;
+
+        fighter_info.id = fighter.pid;
+        fighter_info.name = fighter.name;
+        fighter_info.level = fighter.level;
+        fighter_info.hp = our_fighter.max_hp;
+        fighter_info.attributes = attributes;
+        battle_fighters.push(our_fighter);
+        fighter_info.look = FighterLook::Player {
+            portrait: PortraitData {
+                mouth: fighter.mouth,
+                hair: fighter.hair,
+                brows: fighter.brows,
+                eyes: fighter.eyes,
+                beards: fighter.beards,
+                nose: fighter.nose,
+                ears: fighter.ears,
+                extra: fighter.extra,
+                horns: fighter.horns,
+                influencer: fighter.influencer,
+            },
+            race: fighter.race,
+            gender: fighter.gender,
         };
+        fighter_info.class = fighter.class;
+        // Dont know, don't care (yet)
+        fighter_info.weapon = ItemData([
+            185204737, 327703, // item somehow. check epic: 327737
+            494, 962, 4, 1, 2, 709, 0, 0, 110873491, 23396352,
+        ]);
+    }
 
-        let max_hp = our_fighter.hit_points(&our_attributes, false, 1, 0);
-        our_fighter.max_hp = max_hp;
-        our_fighter.current_hp = max_hp;
-        battle_fighters.push(our_fighter);
+    resp.add_section(&FightHeader {
+        kind: FightKind::Arena,
+        location: 0,
+        fighters: fighter_infos,
+    });
 
-        // Player info
-        resp.add_val(fighter.pid);
-        resp.add_str(&fighter.name);
-        resp.add_val(fighter.level);
-        resp.add_val(max_hp);
-        resp.add_val(max_hp);
-        resp.add_val(fighter.strength); // str
-        resp.add_val(fighter.dexterity); // dex
-        resp.add_val(fighter.intelligence); // int
-        resp.add_val(fighter.stamina); // const
-        resp.add_val(fighter.luck); // luck
-        resp.add_val(fighter.mouth); // mouth
-        resp.add_val(fighter.hair); // hair
-        resp.add_val(fighter.eyes); // brows
-        resp.add_val(fighter.eyes); // eyes
-        resp.add_val(fighter.beards); // beards
-        resp.add_val(fighter.nose); // nose
-        resp.add_val(fighter.ears); // ears
-        resp.add_val(fighter.extra); // extra
-        resp.add_val(fighter.horns); // horns
-        resp.add_val(fighter.influencer); // influencer
-        resp.add_val(fighter.race);
-        resp.add_val(fighter.gender);
-        resp.add_val(fighter.class);
-        // Dont know, don't care (yet)
-        resp.add_val(185204737);
-        resp.add_val(327703); // item somehow. check epic: 327737
-        resp.add_val(494);
-        resp.add_val(962);
-        resp.add_val(4);
-        resp.add_val(1);
-        resp.add_val(2);
-        resp.add_val(709);
-        resp.add_val(0);
-        resp.add_val(0);
-        resp.add_val(110873491);
-        resp.add_val(23396352);
-        // Some item i think
-        for _ in 0..12 {
-            resp.add_val(0);
-        }
+    let [left, right] = <[BattleFighter; 2]>::try_from(battle_fighters)
+        .map_err(|_| ServerError::Internal)?;
+    let won = simulate_fight(&mut resp, fighters, left, right);
+
+    resp.add_key("winnerid");
+    resp.add_val(if won { fighters[0] } else { fighters[1] });
+    resp.add_section(&FightResult {
+        won,
+        kind: FightKind::Arena,
+        rank_pre: 2,
+        rank_post: 2,
+        ..Default::default()
+    });
+    resp.build()
+}
+
+/// Builds a fighter for the battle simulation with full hp. The class is the
+/// one we store (1 based) and the attributes are strength, dexterity,
+/// intelligence, constitution and luck
+fn battle_fighter(
+    level: i64,
+    class: i64,
+    attributes: [i64; 5],
+    weapon: (u32, u32),
+) -> Result<BattleFighter, ServerError> {
+    let class = Class::from_i64(class - 1).ok_or(ServerError::Internal)?;
+
+    let mut attribute_map: EnumMap<AttributeType, u32> = EnumMap::default();
+    for (typ, val) in [
+        AttributeType::Strength,
+        AttributeType::Dexterity,
+        AttributeType::Intelligence,
+        AttributeType::Constitution,
+        AttributeType::Luck,
+    ]
+    .into_iter()
+    .zip(attributes)
+    {
+        attribute_map[typ] = val as u32;
     }
 
+    let equip = EquipmentEffects {
+        element_res: EnumMap::default(),
+        element_dmg: EnumMap::default(),
+        weapon,
+        offhand: (0, 0),
+        reaction_boost: false,
+        extra_crit_dmg: false,
+        armor: 0,
+    };
+    let mut fighter = BattleFighter {
+        level: level as u16,
+        is_companion: false,
+        class,
+        attributes: attribute_map,
+        max_hp: 0,
+        current_hp: 0,
+        equip,
+        rounds_in_battle: 0,
+        class_effect: ClassEffect::Normal,
+        portal_dmg_bonus: 1.0,
+    };
+
+    let max_hp = fighter.hit_points(&attribute_map, false, 1, 0);
+    fighter.max_hp = max_hp;
+    fighter.current_hp = max_hp;
+    Ok(fighter)
+}
+
+/// Simulates the fight between the two fighters and writes the log of it
+/// (`fight.r`). The ids are the ones of the fighters in the fight header.
+/// Returns whether the left fighter has won
+fn simulate_fight(
+    resp: &mut ResponseBuilder,
+    ids: [i64; 2],
+    left: BattleFighter,
+    right: BattleFighter,
+) -> bool {
     resp.add_key("fight.r");
 
-    let bf1 = battle_fighters.get(0).unwrap().clone();
-    let bf2 = battle_fighters.get(1).unwrap().clone();
-    let mut bf_left = [bf1];
-    let mut bf_right = [bf2];
-    
+    let mut bf_left = [left];
+    let mut bf_right = [right];
+
     let mut battle = Battle::new(&mut bf_left, &mut bf_right);
     battle.simulate_turn(&mut ()); // first turn hits the air for some reason
     loop {
         battle.simulate_turn(&mut ());
         // TODO: remove this. hack to avoid a friendly fire round
-        battle.round += if battle.round == 2 && battle.started.unwrap() == BattleSide::Left {
+        battle.round += if battle.round == 2
+            && battle.started == Some(BattleSide::Left)
+        {
             1
         } else {
             0
@@ -914,7 +1223,7 @@ pub(crate) async fn player_arena_fight(
             Some(f) => f.current_hp,
             None => 0,
         };
-        resp.add_val(fighters[(battle.round % 2) as usize]);
+        resp.add_val(ids[(battle.round % 2) as usize]);
         resp.add_val(0);
         resp.add_val(0); // Attack type (normal=0, crit=1, catapult, etc.)
         resp.add_val(0); // Enemy reaction (repelled/dodged)
@@ -933,34 +1242,31 @@ pub(crate) async fn player_arena_fight(
         }
     }
 
-    let left_hp = match battle.left.current() {
-        Some(f) => f.current_hp,
-        None => 0,
-    };
+    match battle.left.current() {
+        Some(f) => f.current_hp > 0,
+        None => false,
+    }
+}
 
-    resp.add_key("winnerid");
-    resp.add_val(if left_hp > 0 {
-        fighters[0]
-    } else {
-        fighters[1]
-    });
-    resp.add_key("fightresult.battlereward");
-    resp.add_val((left_hp > 0) as i32); // have we won?
-    resp.add_val(1);
-    resp.add_val(0); // silver
-    resp.add_val(0); // xp won
-    resp.add_val(if left_hp > 0 {
-        1337
-    } else {
-        0
-    }); // mushrooms
-    resp.add_val(0); // honor won
-    resp.add_val(0);
-    resp.add_val(2); // rank pre
-    resp.add_val(2); // rank post
-                     // Item
-    for _ in 0..12 {
-        resp.add_val(0);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beer_limits_do_not_stack() {
+        assert_eq!(beer_limit(false, false), 10);
+        assert_eq!(beer_limit(true, false), 11);
+        assert_eq!(beer_limit(false, true), 11);
+        assert_eq!(beer_limit(true, true), 11);
+    }
+
+    #[test]
+    fn fighters_use_the_stored_class() {
+        // Classes are stored 1 based, the simulation starts at 0
+        let fighter = battle_fighter(1, 1, [10; 5], (1, 2)).unwrap();
+        assert_eq!(fighter.class, Class::Warrior);
+        let fighter = battle_fighter(1, 2, [10; 5], (1, 2)).unwrap();
+        assert_eq!(fighter.class, Class::Mage);
+        assert!(battle_fighter(1, 0, [10; 5], (1, 2)).is_err());
     }
-    resp.build()
 }
@@ -0,0 +1,367 @@
+use fastrand::Rng;
+use log::warn;
+use sf_api::gamestate::character::Class;
+use sqlx::Transaction;
+
+use super::{
+    item::{insert_item, random_equipment, RawItem},
+    xp_for_next_level,
+};
+use crate::{
+    db::{Backend, DbPool},
+    ServerError,
+};
+
+/// A place quests can lead to and the monsters, that can be encountered
+/// there. Monsters get stronger the higher their id is, so later locations
+/// unlock with higher levels.
+///
+/// NOTE: This is a placeholder. The game does not give every location 12
+/// consecutive monsters and the level requirements are made up. It has to be
+/// replaced with the official location to monster mapping
+struct QuestLocation {
+    id: i64,
+    min_level: i64,
+    monsters: (i64, i64),
+}
+
+const fn location(
+    id: i64,
+    min_level: i64,
+    monsters: (i64, i64),
+) -> QuestLocation {
+    QuestLocation {
+        id,
+        min_level,
+        monsters,
+    }
+}
+
+static LOCATIONS: [QuestLocation; 21] = [
+    location(1, 1, (1, 12)),
+    location(2, 1, (13, 24)),
+    location(3, 1, (25, 36)),
+    location(4, 5, (37, 48)),
+    location(5, 10, (49, 60)),
+    location(6, 15, (61, 72)),
+    location(7, 20, (73, 84)),
+    location(8, 25, (85, 96)),
+    location(9, 30, (97, 108)),
+    location(10, 35, (109, 120)),
+    location(11, 40, (121, 132)),
+    location(12, 45, (133, 144)),
+    location(13, 50, (145, 156)),
+    location(14, 60, (157, 168)),
+    location(15, 70, (169, 180)),
+    location(16, 80, (181, 192)),
+    location(17, 90, (193, 204)),
+    location(18, 100, (205, 216)),
+    location(19, 125, (217, 228)),
+    location(20, 150, (229, 240)),
+    location(21, 200, (241, 252)),
+];
+
+/// How a kind of monster fights and how its attributes are distributed
+struct MonsterTemplate {
+    class: Class,
+    /// Strength, dexterity, intelligence, constitution and luck in percent of
+    /// the base attribute value at the level of the monster
+    attributes: [i64; 5],
+}
+
+const fn monster(class: Class, attributes: [i64; 5]) -> MonsterTemplate {
+    MonsterTemplate { class, attributes }
+}
+
+/// The kinds of quest monsters. Every monster id maps to one of these.
+///
+/// NOTE: Like `LOCATIONS`, this is a placeholder until we have the real
+/// stats of every monster
+static MONSTERS: [MonsterTemplate; 6] = [
+    monster(Class::Warrior, [120, 40, 30, 100, 40]),
+    monster(Class::Mage, [30, 40, 120, 60, 50]),
+    monster(Class::Scout, [40, 120, 30, 80, 60]),
+    monster(Class::Warrior, [100, 50, 30, 130, 30]),
+    monster(Class::Scout, [40, 100, 30, 70, 100]),
+    monster(Class::Mage, [30, 50, 100, 80, 40]),
+];
+
+/// A quest monster, scaled to the level of the character fighting it
+#[derive(Debug, Clone)]
+pub(crate) struct QuestMonster {
+    pub class: Class,
+    pub level: i64,
+    pub attributes: [i64; 5],
+    pub weapon: (u32, u32),
+}
+
+/// Looks up the stats of the monster with the given id for a fight against a
+/// character of the given level
+pub(crate) fn quest_monster(monster: i64, level: i64) -> QuestMonster {
+    let level = level.max(1);
+    let template = &MONSTERS[monster.unsigned_abs() as usize % MONSTERS.len()];
+    // Monsters are a bit weaker than what a character of the same level
+    // would have bought, so that a well equipped character wins most fights
+    let base = 5 + level * 4;
+    QuestMonster {
+        class: template.class,
+        level,
+        attributes: template.attributes.map(|a| base * a / 100),
+        weapon: (level as u32, level as u32 * 2),
+    }
+}
+
+/// How many variations of its text every quest has. The client picks the
+/// quest name, giver and text from the flavours and the monster
+const QUEST_FLAVOURS: i64 = 5;
+
+/// The chance (in percent) of a quest rewarding an item
+const ITEM_CHANCE: u32 = 25;
+/// The chance (in percent) of a quest rewarding a mushroom
+const MUSHROOM_CHANCE: u32 = 10;
+
+/// Everything a quest in the tavern consists of
+#[derive(Debug, Clone)]
+pub(crate) struct GeneratedQuest {
+    pub flavour1: i64,
+    pub flavour2: i64,
+    pub monster: i64,
+    pub location: i64,
+    /// The length in seconds, before any mount is applied
+    pub length: i64,
+    pub xp: i64,
+    pub silver: i64,
+    pub mushrooms: i64,
+    pub item: Option<RawItem>,
+}
+
+/// Generates a new quest for a character of the given level and class
+pub(crate) fn generate_quest(
+    rng: &mut Rng,
+    level: i64,
+    class: i64,
+) -> GeneratedQuest {
+    let level = level.max(1);
+    let locations: Vec<_> =
+        LOCATIONS.iter().filter(|a| a.min_level <= level).collect();
+    let location = locations[rng.usize(..locations.len())];
+    let monster = rng.i64(location.monsters.0..=location.monsters.1);
+
+    // Quests start out short and get longer with the level, until they take
+    // up to 20 minutes
+    let max_minutes = (3 + level / 5).min(20);
+    let length = rng.i64(1..=max_minutes) * 60;
+    let minutes = length / 60;
+
+    // Spending all 100 minutes of thirst should roughly get a character
+    // one level further
+    let variance = |rng: &mut Rng| 0.8 + rng.f64() * 0.4;
+    let xp = (xp_for_next_level(level) as f64 * minutes as f64 / 100.0
+        * variance(rng)) as i64;
+    let silver =
+        ((level * 10 + 25) as f64 * minutes as f64 * variance(rng)) as i64;
+    let mushrooms = (rng.u32(..100) < MUSHROOM_CHANCE) as i64;
+    let item = (rng.u32(..100) < ITEM_CHANCE)
+        .then(|| random_equipment(rng, level, class));
+
+    GeneratedQuest {
+        flavour1: rng.i64(1..=QUEST_FLAVOURS),
+        flavour2: rng.i64(1..=QUEST_FLAVOURS),
+        monster,
+        location: location.id,
+        length,
+        xp: xp.max(1),
+        silver: silver.max(1),
+        mushrooms,
+        item,
+    }
+}
+
+/// Stores the quest (and its item) and returns the id of the new quest row
+pub(crate) async fn insert_quest(
+    tx: &mut Transaction<'_, Backend>,
+    quest: &GeneratedQuest,
+) -> Result<i64, ServerError> {
+    let item_id = match &quest.item {
+        Some(item) => Some(insert_item(tx, item).await?),
+        None => None,
+    };
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO quest (monster, location, length, xp, silver, mushrooms, \
+         item, flavour1, flavour2)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+        quest.monster,
+        quest.location,
+        quest.length,
+        quest.xp,
+        quest.silver,
+        quest.mushrooms,
+        item_id,
+        quest.flavour1,
+        quest.flavour2,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(id)
+}
+
+/// Replaces all three tavern quests of the character with new ones. The
+/// items of the old quests are deleted, so anything a player has been
+/// rewarded with has to be unlinked from its quest before this
+pub(crate) async fn reroll_quests(
+    tx: &mut Transaction<'_, Backend>,
+    rng: &mut Rng,
+    pid: i64,
+    level: i64,
+    class: i64,
+) -> Result<(), ServerError> {
+    let old = sqlx::query!(
+        "SELECT quest1, quest2, quest3 FROM tavern WHERE pid = $1", pid
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let mut new = [0; 3];
+    for id in &mut new {
+        *id = insert_quest(tx, &generate_quest(rng, level, class)).await?;
+    }
+
+    sqlx::query!(
+        "UPDATE tavern SET quest1 = $2, quest2 = $3, quest3 = $4
+         WHERE pid = $1",
+        pid,
+        new[0],
+        new[1],
+        new[2],
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    for id in [old.quest1, old.quest2, old.quest3] {
+        sqlx::query!(
+            "DELETE FROM item WHERE id = (SELECT item FROM quest WHERE id = \
+             $1)",
+            id
+        )
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query!("DELETE FROM quest WHERE id = $1", id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Finds characters, whose tavern or activity the quest handlers can not deal
+/// with, and repairs them. Broken quests get rerolled and invalid activities
+/// are cancelled with a refund. Returns how many repairs were necessary
+pub async fn repair_quests(db: &DbPool) -> Result<u64, ServerError> {
+    let mut repaired = 0;
+
+    let missing = sqlx::query!(
+        "INSERT INTO activity (pid)
+         SELECT pid FROM character
+         WHERE pid NOT IN (SELECT pid FROM activity)"
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+    repaired += missing;
+
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "UPDATE tavern
+         SET tfa = tfa + (SELECT thirst_spent FROM activity as a
+                          WHERE a.pid = tavern.pid)
+         WHERE pid IN (SELECT pid FROM activity
+                       WHERE typ = 2 AND sub_type NOT BETWEEN 1 AND 3)"
+    )
+    .execute(&mut *tx)
+    .await?;
+    let invalid = sqlx::query!(
+        "UPDATE activity
+         SET typ = 0, sub_type = 0, started = 0, busy_until = 0,
+             thirst_spent = 0
+         WHERE typ NOT IN (0, 1, 2)
+            OR (typ = 1 AND sub_type NOT BETWEEN 1 AND 10)
+            OR (typ = 2 AND sub_type NOT BETWEEN 1 AND 3)"
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+    repaired += invalid;
+
+    let broken = sqlx::query!(
+        "SELECT c.pid as \"pid!\", c.level as \"level!\",
+                c.class as \"class!\",
+                t.pid IS NOT NULL as \"has_tavern!: bool\"
+         FROM character as c
+            LEFT JOIN tavern as t on t.pid = c.pid
+            LEFT JOIN quest as q1 on q1.id = t.quest1
+            LEFT JOIN quest as q2 on q2.id = t.quest2
+            LEFT JOIN quest as q3 on q3.id = t.quest3
+         WHERE t.pid IS NULL
+            OR q1.id IS NULL OR q2.id IS NULL OR q3.id IS NULL
+            OR t.quest1 = t.quest2 OR t.quest1 = t.quest3
+            OR t.quest2 = t.quest3"
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut rng = Rng::new();
+    for character in &broken {
+        warn!("Repairing the tavern quests of character {}", character.pid);
+        let mut tx = db.begin().await?;
+        if character.has_tavern {
+            reroll_quests(
+                &mut tx, &mut rng, character.pid, character.level,
+                character.class,
+            )
+            .await?;
+        } else {
+            let mut quests = [0; 3];
+            for quest in &mut quests {
+                let new_quest =
+                    generate_quest(&mut rng, character.level, character.class);
+                *quest = insert_quest(&mut tx, &new_quest).await?;
+            }
+            sqlx::query!(
+                "INSERT INTO tavern (pid, quest1, quest2, quest3)
+                 VALUES ($1, $2, $3, $4)",
+                character.pid,
+                quests[0],
+                quests[1],
+                quests[2],
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+    }
+    repaired += broken.len() as u64;
+
+    if repaired > 0 {
+        warn!("Repaired {repaired} characters with broken quests");
+    }
+    Ok(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quests_roll_their_flavours() {
+        let mut rng = Rng::with_seed(0);
+        let quests: Vec<_> =
+            (0..100).map(|_| generate_quest(&mut rng, 10, 1)).collect();
+        for quest in &quests {
+            assert!((1..=QUEST_FLAVOURS).contains(&quest.flavour1));
+            assert!((1..=QUEST_FLAVOURS).contains(&quest.flavour2));
+        }
+        assert!(quests.iter().any(|a| a.flavour1 != 1));
+        assert!(quests.iter().any(|a| a.flavour2 != 1));
+    }
+}
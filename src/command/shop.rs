@@ -0,0 +1,227 @@
+use fastrand::Rng;
+use sqlx::Transaction;
+
+use super::{
+    item::{
+        equipment_types, generate_item, insert_item, load_items, main_class,
+        Rarity, RawItemTyp,
+    },
+    update::poll,
+    InRange, ServerError, ServerResponse,
+};
+use crate::{
+    db::{Backend, DbPool},
+    request::Session,
+    response::Shop,
+};
+
+/// The inventory id of the weapon shop, that the client uses to buy from, or
+/// sell to it
+pub(crate) const WEAPON_SHOP: i64 = 3;
+/// The inventory id of the magic shop
+pub(crate) const MAGIC_SHOP: i64 = 4;
+
+/// What new wares cost in mushrooms
+const REROLL_PRICE: i64 = 1;
+
+/// The weapon shop sells weapons, shields, armor and helmets. The magic shop
+/// sells everything else a character can wear: boots, gloves, belts and
+/// jewelry
+fn sells(shop: i64, typ: RawItemTyp) -> bool {
+    let weapon_shop = matches!(
+        typ,
+        RawItemTyp::Weapon
+            | RawItemTyp::Shield
+            | RawItemTyp::BreastPlate
+            | RawItemTyp::Hat
+    );
+    weapon_shop == (shop == WEAPON_SHOP)
+}
+
+/// Replaces the wares of the shop with new items for a character of the
+/// given level and (1 based) class
+pub(crate) async fn reroll_shop(
+    tx: &mut Transaction<'_, Backend>,
+    rng: &mut Rng,
+    pid: i64,
+    level: i64,
+    class: i64,
+    shop: i64,
+) -> Result<(), ServerError> {
+    let old = sqlx::query!(
+        "SELECT item1, item2, item3, item4, item5, item6
+         FROM shop WHERE pid = $1 AND typ = $2",
+        pid,
+        shop
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(old) = old {
+        let old = [
+            old.item1, old.item2, old.item3, old.item4, old.item5, old.item6,
+        ];
+        for id in old.into_iter().flatten() {
+            sqlx::query!("DELETE FROM item WHERE id = $1", id)
+                .execute(&mut **tx)
+                .await?;
+        }
+    }
+
+    let types: Vec<_> = equipment_types(main_class(class).0)
+        .into_iter()
+        .filter(|typ| sells(shop, *typ))
+        .collect();
+    let mut new = [0; 6];
+    for id in &mut new {
+        let typ = types[rng.usize(..types.len())];
+        let rarity = Rarity::roll(rng);
+        let item = generate_item(rng, level, class, typ, rarity);
+        *id = insert_item(tx, &item).await?;
+    }
+
+    sqlx::query!(
+        "INSERT INTO shop (pid, typ, item1, item2, item3, item4, item5, item6)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         ON CONFLICT (pid, typ) DO UPDATE
+         SET item1 = excluded.item1, item2 = excluded.item2,
+             item3 = excluded.item3, item4 = excluded.item4,
+             item5 = excluded.item5, item6 = excluded.item6",
+        pid,
+        shop,
+        new[0],
+        new[1],
+        new[2],
+        new[3],
+        new[4],
+        new[5],
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// The wares of the shop. Characters, that have never had their shops
+/// filled, see an empty shop
+pub(crate) async fn shop_items(
+    db: &DbPool,
+    pid: i64,
+    shop: i64,
+) -> Result<Shop, ServerError> {
+    let row = sqlx::query!(
+        "SELECT item1, item2, item3, item4, item5, item6
+         FROM shop WHERE pid = $1 AND typ = $2",
+        pid,
+        shop
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(row) = row else {
+        return Ok(Shop::default());
+    };
+    let ids = [
+        row.item1, row.item2, row.item3, row.item4, row.item5, row.item6,
+    ];
+    Ok(Shop {
+        items: load_items(db, ids).await?,
+    })
+}
+
+/// Takes the item at the (1 based) position out of the shop and charges the
+/// character for it. Returns the id of the bought item
+pub(crate) async fn buy_item(
+    tx: &mut Transaction<'_, Backend>,
+    pid: i64,
+    shop: i64,
+    pos: i64,
+) -> Result<i64, ServerError> {
+    if !(1..=6).contains(&pos) {
+        return Err(ServerError::BadRequest);
+    }
+    let item: Option<Option<i64>> = sqlx::query_scalar(&format!(
+        "SELECT item{pos} FROM shop WHERE pid = $1 AND typ = $2"
+    ))
+    .bind(pid)
+    .bind(shop)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let item = item.flatten().ok_or(ServerError::BadRequest)?;
+
+    let price = sqlx::query!(
+        "SELECT item.silver, item.mushrooms,
+                character.silver as money, character.mushrooms as coins
+         FROM item, character
+         WHERE item.id = $1 AND character.pid = $2",
+        item,
+        pid
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    if price.money < price.silver {
+        return Err(ServerError::NotEnoughMoney);
+    }
+    if price.coins < price.mushrooms {
+        return Err(ServerError::NotEnoughMushrooms);
+    }
+
+    sqlx::query!(
+        "UPDATE character
+         SET silver = silver - $2, mushrooms = mushrooms - $3
+         WHERE pid = $1",
+        pid,
+        price.silver,
+        price.mushrooms,
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query(&format!(
+        "UPDATE shop SET item{pos} = NULL WHERE pid = $1 AND typ = $2"
+    ))
+    .bind(pid)
+    .bind(shop)
+    .execute(&mut **tx)
+    .await?;
+    Ok(item)
+}
+
+command_args! {
+    pub(crate) struct NewWaresArgs {
+        /// 3 for the weapon shop and 4 for the magic shop
+        shop: InRange<3, 4>,
+    }
+}
+
+/// Pays a mushroom to get new wares in one of the shops right away
+pub(crate) async fn player_new_wares(
+    session: Session,
+    db: &DbPool,
+    args: NewWaresArgs,
+) -> Result<ServerResponse, ServerError> {
+    let mut rng = Rng::new();
+    let mut tx = db.begin().await?;
+
+    let character = sqlx::query!(
+        "SELECT level, class, mushrooms FROM character WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if character.mushrooms < REROLL_PRICE {
+        return Err(ServerError::NotEnoughMushrooms);
+    }
+    sqlx::query!(
+        "UPDATE character SET mushrooms = mushrooms - $2 WHERE pid = $1",
+        session.player_id, REROLL_PRICE
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    reroll_shop(
+        &mut tx, &mut rng, session.player_id, character.level, character.class,
+        args.shop.0,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
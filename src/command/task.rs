@@ -0,0 +1,117 @@
+use fastrand::Rng;
+use sqlx::Transaction;
+
+use super::ServerError;
+use crate::db::{Backend, DbPool};
+
+/// How many daily tasks a character gets every day
+const DAILY_TASKS: usize = 3;
+
+/// The mushrooms a character gets for finishing a daily task
+pub(crate) const TASK_REWARD: i64 = 1;
+
+/// A daily task of a character, as the client gets to see it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DailyTask {
+    pub typ: i64,
+    pub progress: i64,
+    pub target: i64,
+}
+
+/// Something a character can be asked to do as a daily task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TaskTyp {
+    FinishQuests = 1,
+    DrinkBeer = 2,
+    FightInArena = 3,
+    PlayDice = 4,
+}
+
+impl TaskTyp {
+    const ALL: [TaskTyp; 4] = [
+        TaskTyp::FinishQuests,
+        TaskTyp::DrinkBeer,
+        TaskTyp::FightInArena,
+        TaskTyp::PlayDice,
+    ];
+
+    /// How often the character has to do this in a day
+    fn target(self, rng: &mut Rng) -> i64 {
+        match self {
+            TaskTyp::FinishQuests => rng.i64(3..=5),
+            TaskTyp::DrinkBeer | TaskTyp::FightInArena => rng.i64(1..=3),
+            TaskTyp::PlayDice => rng.i64(2..=4),
+        }
+    }
+}
+
+/// Replaces the daily tasks of the character with new ones for the day
+pub(crate) async fn roll_daily_tasks(
+    tx: &mut Transaction<'_, Backend>,
+    rng: &mut Rng,
+    pid: i64,
+) -> Result<(), ServerError> {
+    sqlx::query!("DELETE FROM daily_task WHERE pid = $1", pid)
+        .execute(&mut **tx)
+        .await?;
+
+    let mut typs = TaskTyp::ALL;
+    rng.shuffle(&mut typs);
+    for (pos, typ) in typs.into_iter().take(DAILY_TASKS).enumerate() {
+        sqlx::query!(
+            "INSERT INTO daily_task (pid, pos, typ, target)
+             VALUES ($1, $2, $3, $4)",
+            pid,
+            pos as i64,
+            typ as i64,
+            typ.target(rng),
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Counts one more of `typ` towards the daily tasks of the character.
+/// Finished tasks stay at their target. The task, that gets finished by
+/// this, pays out `TASK_REWARD` right away
+pub(crate) async fn progress_task(
+    tx: &mut Transaction<'_, Backend>,
+    pid: i64,
+    typ: TaskTyp,
+) -> Result<(), ServerError> {
+    let finished = sqlx::query_scalar!(
+        "UPDATE daily_task SET progress = progress + 1
+         WHERE pid = $1 AND typ = $2 AND progress < target
+         RETURNING progress >= target as \"finished!: bool\"",
+        pid,
+        typ as i64
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    if finished == Some(true) {
+        sqlx::query!(
+            "UPDATE character SET mushrooms = mushrooms + $2 WHERE pid = $1",
+            pid, TASK_REWARD
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The daily tasks of the character in the order, that they were rolled in
+pub(crate) async fn daily_tasks(
+    db: &DbPool,
+    pid: i64,
+) -> Result<Vec<DailyTask>, ServerError> {
+    let tasks = sqlx::query_as!(
+        DailyTask,
+        "SELECT typ, progress, target FROM daily_task
+         WHERE pid = $1 ORDER BY pos",
+        pid
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(tasks)
+}
@@ -1,19 +1,27 @@
-use sf_api::{
-    command::AttributeType, gamestate::items::EquipmentSlot, misc::to_sf_string,
-};
-use sqlx::Sqlite;
-use strum::IntoEnumIterator;
+use sf_api::misc::to_sf_string;
 
 use super::{
-    effective_mount, get_debug_value_default, in_seconds, item::add_debug_item,
-    now, xp_for_next_level, ResponseBuilder, ServerError, ServerResponse,
+    active_events,
+    attributes::attribute_price,
+    effective_mount, get_debug_value_default, in_seconds,
+    item::{bag_items, equipment_bonus, equipment_items, load_items},
+    mounted_quest_length, now,
+    shop::shop_items,
+    task::{daily_tasks, TASK_REWARD},
+    xp_for_next_level, ResponseBuilder, ServerError, ServerResponse,
+    MAGIC_SHOP, WEAPON_SHOP,
+};
+use crate::{
+    db::DbPool,
+    request::Session,
+    response::{PlayerSave, PortraitData, QuestOffer, Tavern},
+    SERVER_VERSION,
 };
-use crate::{request::Session, SERVER_VERSION};
 
 pub(crate) async fn poll(
     session: Session,
     tracking: &str,
-    db: &sqlx::Pool<Sqlite>,
+    db: &DbPool,
     mut builder: ResponseBuilder,
 ) -> Result<ServerResponse, ServerError> {
     let resp = builder
@@ -80,6 +88,10 @@ pub(crate) async fn poll(
         q2.SILVER as q2silver,
         q3.SILVER as q3silver,
 
+        q1.item as q1item,
+        q2.item as q2item,
+        q3.item as q3item,
+
         tavern.tfa,
         tavern.Beer_Drunk,
 
@@ -91,12 +103,27 @@ pub(crate) async fn poll(
         character.mushrooms,
         character.silver,
         tavern.QuickSand, -- 50
+        character.wood,
+        character.stone,
+        character.arena_next_free,
 
         description,
         character.name,
 
         portrait.influencer,
 
+        a.strength,
+        a.dexterity,
+        a.intelligence,
+        a.stamina,
+        a.luck,
+
+        b.strength as bought_strength,
+        b.dexterity as bought_dexterity,
+        b.intelligence as bought_intelligence,
+        b.stamina as bought_stamina,
+        b.luck as bought_luck,
+
         (
         SELECT count(*)
         FROM CHARACTER AS x
@@ -104,12 +131,12 @@ pub(crate) async fn poll(
           AND (x.honor > character.honor
                OR (x.honor = character.honor
                    AND x.pid <= character.pid))
-        )  as `rank!: i64`,
+        )  as \"rank!: i64\",
         (
         SELECT count(*)
         FROM CHARACTER AS x
         WHERE x.world_id = character.world_id
-        )  as `maxrank!: i64`
+        )  as \"maxrank!: i64\"
 
         FROM CHARACTER
          NATURAL JOIN activity
@@ -117,7 +144,9 @@ pub(crate) async fn poll(
          NATURAL JOIN portrait
          JOIN quest as q1 on tavern.quest1 = q1.id
          JOIN quest as q2 on tavern.quest2 = q2.id
-         JOIN quest as q3 on tavern.quest2 = q3.id
+         JOIN quest as q3 on tavern.quest3 = q3.id
+         JOIN attributes as a on character.attributes = a.id
+         JOIN attributes as b on character.attributes_bought = b.id
          WHERE character.pid = $1",
         session.player_id
     )
@@ -165,21 +194,25 @@ pub(crate) async fn poll(
     resp.add_val(0);
 
     resp.add_key("tavernspecialsub");
-    resp.add_val(0);
+    resp.add_val(active_events());
 
     resp.add_key("tavernspecialend");
     resp.add_val(-1);
 
-    resp.add_key("attbonus1(3)");
-    resp.add_str("0/0/0/0");
-    resp.add_key("attbonus2(3)");
-    resp.add_str("0/0/0/0");
-    resp.add_key("attbonus3(3)");
-    resp.add_str("0/0/0/0");
-    resp.add_key("attbonus4(3)");
-    resp.add_str("0/0/0/0");
-    resp.add_key("attbonus5(3)");
-    resp.add_str("0/0/0/0");
+    let attributes = [
+        char.strength, char.dexterity, char.intelligence, char.stamina,
+        char.luck,
+    ];
+    let attributes_bought = [
+        char.bought_strength, char.bought_dexterity, char.bought_intelligence,
+        char.bought_stamina, char.bought_luck,
+    ];
+
+    // The price of the next point of each attribute
+    for (i, bought) in attributes_bought.iter().enumerate() {
+        resp.add_key(&format!("attbonus{}(3)", i + 1));
+        resp.add_str(&format!("{}/0/0/0", attribute_price(*bought)));
+    }
 
     resp.add_key("stoneperhournextlevel");
     resp.add_val(50);
@@ -193,495 +226,101 @@ pub(crate) async fn poll(
     resp.add_key("inboxcapacity");
     resp.add_val(100);
 
-    resp.add_key("ownplayersave.playerSave");
-    resp.add_val(403127023); // What is this?
-    resp.add_val(session.player_id);
-    resp.add_val(0);
-    resp.add_val(1708336503);
-    resp.add_val(1292388336);
-    resp.add_val(0);
-    resp.add_val(0);
-    let level = char.level;
-    resp.add_val(level); // Level | Arena << 16
-    resp.add_val(char.experience); // Experience
-    resp.add_val(xp_for_next_level(level)); // Next Level XP
-    let honor = char.honor;
-    resp.add_val(honor); // Honor
-    let rank = char.rank;
-    resp.add_val(rank); // Rank
-
-    resp.add_val(0); // 12?
-    resp.add_val(10); // 13?
-    resp.add_val(0); // 14?
-    resp.add_val(char.mushrooms); // Mushroms gained
-    resp.add_val(0); // 16?
-
-    // Portrait start
-    resp.add_val(char.mouth); // mouth
-    resp.add_val(char.hair); // hair
-    resp.add_val(char.brows); // brows
-    resp.add_val(char.eyes); // eyes
-    resp.add_val(char.beards); // beards
-    resp.add_val(char.nose); // nose
-    resp.add_val(char.ears); // ears
-    resp.add_val(char.extra); // extra
-    resp.add_val(char.horns); // horns
-    resp.add_val(char.influencer); // influencer
-    resp.add_val(char.race); // race
-    resp.add_val(char.gender); // Gender & Mirror
-    resp.add_val(char.class); // class
-
-    // Attributes
-    for _ in AttributeType::iter() {
-        resp.add_val(100); // 30..=34
-    }
-
-    // attribute_additions (aggregate from equipment)
-    for _ in AttributeType::iter() {
-        resp.add_val(0); // 35..=38
-    }
-
-    // attribute_times_bought
-    for _ in AttributeType::iter() {
-        resp.add_val(0); // 40..=44
-    }
-
-    resp.add_val(char.activitytyp); // Current action
-    resp.add_val(char.activitysubtyp); // Secondary (time busy)
-    resp.add_val(char.busy_until); // Busy until
-
-    // Equipment
-    for slot in [
-        EquipmentSlot::Hat,
-        EquipmentSlot::BreastPlate,
-        EquipmentSlot::Gloves,
-        EquipmentSlot::FootWear,
-        EquipmentSlot::Amulet,
-        EquipmentSlot::Belt,
-        EquipmentSlot::Ring,
-        EquipmentSlot::Talisman,
-        EquipmentSlot::Weapon,
-        EquipmentSlot::Shield,
-    ] {
-        add_debug_item(resp, format!("{slot:?}").to_lowercase());
-    }
-    add_debug_item(resp, "inventory1");
-    add_debug_item(resp, "inventory2");
-    add_debug_item(resp, "inventory3");
-    add_debug_item(resp, "inventory4");
-    add_debug_item(resp, "inventory5");
-
-    resp.add_val(in_seconds(60 * 60)); // 228
-
-    // Ok, so Flavour 1, Flavour 2 & Monster ID decide =>
-    // - The Line they say
-    // - the quest name
-    // - the quest giver
-    resp.add_val(char.q1f1); // 229 Quest1 Flavour1
-    resp.add_val(char.q2f1); // 230 Quest2 Flavour1
-    resp.add_val(char.q3f1); // 231 Quest3 Flavour1
-
-    resp.add_val(char.q1f2); // 233 Quest2 Flavour2
-    resp.add_val(char.q2f2); // 232 Quest1 Flavour2
-    resp.add_val(char.q3f2); // 234 Quest3 Flavour2
-
-    resp.add_val(-char.q1monster); // 235 quest 1 monster
-    resp.add_val(-char.q2monster); // 236 quest 2 monster
-    resp.add_val(-char.q3monster); // 237 quest 3 monster
-
-    resp.add_val(char.q1location); // 238 quest 1 location
-    resp.add_val(char.q2location); // 239 quest 2 location
-    resp.add_val(char.q3location); // 240 quest 3 location
-
     let mut mount_end = char.mount_end;
     let mut mount = char.mount;
 
     let mount_effect = effective_mount(&mut mount_end, &mut mount);
-
-    resp.add_val((char.q1length as f32 * mount_effect) as i32); // 241 quest 1 length
-    resp.add_val((char.q2length as f32 * mount_effect) as i32); // 242 quest 2 length
-    resp.add_val((char.q3length as f32 * mount_effect) as i32); // 243 quest 3 length
-
-    // Quest 1..=3 items
-    for _ in 0..3 {
-        for _ in 0..12 {
-            resp.add_val(0); // 244..=279
-        }
-    }
-
-    resp.add_val(char.q1xp); // 280 quest 1 xp
-    resp.add_val(char.q2xp); // 281 quest 2 xp
-    resp.add_val(char.q3xp); // 282 quest 3 xp
-
-    resp.add_val(char.q1silver); // 283 quest 1 silver
-    resp.add_val(char.q2silver); // 284 quest 2 silver
-    resp.add_val(char.q3silver); // 285 quest 3 silver
-
-    resp.add_val(mount); // Mount?
-
-    // Weapon shop
-    resp.add_val(1708336503); // 287
-    for _ in 0..6 {
-        add_debug_item(resp, "weapon");
-    }
-
-    // Magic shop
-    resp.add_val(1708336503); // 360
-    for _ in 0..6 {
-        add_debug_item(resp, "weapon");
-    }
-
-    resp.add_val(0); // 433
-    resp.add_val(1); // 434 might be tutorial related?
-    resp.add_val(0); // 435
-    resp.add_val(0); // 436
-    resp.add_val(0); // 437
-
-    resp.add_val(0); // 438 scrapbook count
-    resp.add_val(0); // 439
-    resp.add_val(0); // 440
-    resp.add_val(0); // 441
-    resp.add_val(0); // 442
-
-    resp.add_val(0); // 443 guild join date
-    resp.add_val(0); // 444
-    resp.add_val(0); // 445 character_hp_bonus << 24, damage_bonus << 16
-    resp.add_val(0); // 446
-    resp.add_val(0); // 447  Armor
-    resp.add_val(6); // 448  Min damage
-    resp.add_val(12); // 449 Max damage
-    resp.add_val(112); // 450
-    resp.add_val(mount_end); // 451 Mount end
-    resp.add_val(0); // 452
-    resp.add_val(0); // 453
-    resp.add_val(0); // 454
-    resp.add_val(1708336503); // 455
-    resp.add_val(char.tfa); // 456 Alu secs
-    resp.add_val(char.beer_drunk); // 457 Beer drunk
-    resp.add_val(0); // 458
-    resp.add_val(0); // 459 dungeon_timer
-    resp.add_val(1708336503); // 460 Next free fight
-    resp.add_val(0); // 461
-    resp.add_val(0); // 462
-    resp.add_val(0); // 463
-    resp.add_val(0); // 464
-    resp.add_val(408); // 465
-    resp.add_val(0); // 466
-    resp.add_val(0); // 467
-    resp.add_val(0); // 468
-    resp.add_val(0); // 469
-    resp.add_val(0); // 470
-    resp.add_val(0); // 471
-    resp.add_val(0); // 472
-    resp.add_val(0); // 473
-    resp.add_val(-111); // 474
-    resp.add_val(0); // 475
-    resp.add_val(0); // 476
-    resp.add_val(4); // 477
-    resp.add_val(1708336504); // 478
-    resp.add_val(0); // 479
-    resp.add_val(0); // 480
-    resp.add_val(0); // 481
-    resp.add_val(0); // 482
-    resp.add_val(0); // 483
-    resp.add_val(0); // 484
-    resp.add_val(0); // 485
-    resp.add_val(0); // 486
-    resp.add_val(0); // 487
-    resp.add_val(0); // 488
-    resp.add_val(0); // 489
-    resp.add_val(0); // 490
-
-    resp.add_val(0); // 491 aura_level (0 == locked)
-    resp.add_val(0); // 492 aura_now
-
-    // Active potions
-    for _ in 0..3 {
-        resp.add_val(0); // typ & size
-    }
-    for _ in 0..3 {
-        resp.add_val(0); // ??
-    }
-    for _ in 0..3 {
-        resp.add_val(0); // expires
-    }
-    resp.add_val(0); // 502
-    resp.add_val(0); // 503
-    resp.add_val(0); // 504
-    resp.add_val(0); // 505
-    resp.add_val(0); // 506
-    resp.add_val(0); // 507
-    resp.add_val(0); // 508
-    resp.add_val(0); // 509
-    resp.add_val(0); // 510
-    resp.add_val(6); // 511
-    resp.add_val(2); // 512
-    resp.add_val(0); // 513
-    resp.add_val(0); // 514
-    resp.add_val(100); // 515 aura_missing
-    resp.add_val(0); // 516
-    resp.add_val(0); // 517
-    resp.add_val(0); // 518
-    resp.add_val(100); // 519
-    resp.add_val(0); // 520
-    resp.add_val(0); // 521
-    resp.add_val(0); // 522
-    resp.add_val(0); // 523
-
-    // Fortress
-    // Building levels
-    resp.add_val(0); // 524
-    resp.add_val(0); // 525
-    resp.add_val(0); // 526
-    resp.add_val(0); // 527
-    resp.add_val(0); // 528
-    resp.add_val(0); // 529
-    resp.add_val(0); // 530
-    resp.add_val(0); // 531
-    resp.add_val(0); // 532
-    resp.add_val(0); // 533
-    resp.add_val(0); // 534
-    resp.add_val(0); // 535
-    resp.add_val(0); // 536
-    resp.add_val(0); // 537
-    resp.add_val(0); // 538
-    resp.add_val(0); // 539
-    resp.add_val(0); // 540
-    resp.add_val(0); // 541
-    resp.add_val(0); // 542
-    resp.add_val(0); // 543
-    resp.add_val(0); // 544
-    resp.add_val(0); // 545
-    resp.add_val(0); // 546
-                     // unit counts
-    resp.add_val(0); // 547
-    resp.add_val(0); // 548
-    resp.add_val(0); // 549
-                     // upgrade_began
-    resp.add_val(0); // 550
-    resp.add_val(0); // 551
-    resp.add_val(0); // 552
-                     // upgrade_finish
-    resp.add_val(0); // 553
-    resp.add_val(0); // 554
-    resp.add_val(0); // 555
-
-    resp.add_val(0); // 556
-    resp.add_val(0); // 557
-    resp.add_val(0); // 558
-    resp.add_val(0); // 559
-    resp.add_val(0); // 560
-    resp.add_val(0); // 561
-
-    // Current resource in store
-    resp.add_val(0); // 562
-    resp.add_val(0); // 563
-    resp.add_val(0); // 564
-                     // max_in_building
-    resp.add_val(0); // 565
-    resp.add_val(0); // 566
-    resp.add_val(0); // 567
-                     // max saved
-    resp.add_val(0); // 568
-    resp.add_val(0); // 569
-    resp.add_val(0); // 570
-
-    resp.add_val(0); // 571 building_upgraded
-    resp.add_val(0); // 572 building_upgrade_finish
-    resp.add_val(0); // 573 building_upgrade_began
-                     // per hour
-    resp.add_val(0); // 574
-    resp.add_val(0); // 575
-    resp.add_val(0); // 576
-    resp.add_val(0); // 577 unknown time stamp
-    resp.add_val(0); // 578
-
-    resp.add_val(0); // 579 wheel_spins_today
-    resp.add_val(now() + 60 * 10); // 580  wheel_next_free_spin
-
-    resp.add_val(0); // 581 ft level
-    resp.add_val(100); // 582 ft honor
-    resp.add_val(0); // 583 rank
-    resp.add_val(900); // 584
-    resp.add_val(300); // 585
-    resp.add_val(0); // 586
-
-    resp.add_val(0); // 587 attack target
-    resp.add_val(0); // 588 attack_free_reroll
-    resp.add_val(0); // 589
-    resp.add_val(0); // 590
-    resp.add_val(0); // 591
-    resp.add_val(0); // 592
-    resp.add_val(3); // 593
-
-    resp.add_val(0); // 594 gem_stone_target
-    resp.add_val(0); // 595 gem_search_finish
-    resp.add_val(0); // 596 gem_search_began
-    resp.add_val(char.tutorial_status); // 597 Pretty sure this is a bit map of which messages have been seen
-    resp.add_val(0); // 598
-
-    // Arena enemies
-    resp.add_val(get_debug_value_default("arena_enemy1", 1)); // 599
-    resp.add_val(get_debug_value_default("arena_enemy2", 1)); // 600
-    resp.add_val(get_debug_value_default("arena_enemy3", 1)); // 601
-
-    resp.add_val(0); // 602
-    resp.add_val(0); // 603
-    resp.add_val(0); // 604
-    resp.add_val(0); // 605
-    resp.add_val(0); // 606
-    resp.add_val(0); // 607
-    resp.add_val(0); // 608
-    resp.add_val(0); // 609
-    resp.add_val(1708336504); // 610
-    resp.add_val(0); // 611
-    resp.add_val(0); // 612
-    resp.add_val(0); // 613
-    resp.add_val(0); // 614
-    resp.add_val(0); // 615
-    resp.add_val(0); // 616
-    resp.add_val(1); // 617
-    resp.add_val(0); // 618
-    resp.add_val(0); // 619
-    resp.add_val(0); // 620
-    resp.add_val(0); // 621
-    resp.add_val(0); // 622
-    resp.add_val(0); // 623 own_treasure_skill
-    resp.add_val(0); // 624 own_instr_skill
-    resp.add_val(0); // 625
-    resp.add_val(30); // 626
-    resp.add_val(0); // 627 hydra_next_battle
-    resp.add_val(0); // 628 remaining_pet_battles
-    resp.add_val(0); // 629
-    resp.add_val(0); // 630
-    resp.add_val(0); // 631
-    resp.add_val(0); // 632
-    resp.add_val(0); // 633
-    resp.add_val(0); // 634
-    resp.add_val(0); // 635
-    resp.add_val(0); // 636
-    resp.add_val(0); // 637
-    resp.add_val(0); // 638
-    resp.add_val(0); // 639
-    resp.add_val(0); // 640
-    resp.add_val(0); // 641
-    resp.add_val(0); // 642
-    resp.add_val(0); // 643
-    resp.add_val(0); // 644
-    resp.add_val(0); // 645
-    resp.add_val(0); // 646
-    resp.add_val(0); // 647
-    resp.add_val(0); // 648
-    resp.add_val(in_seconds(60 * 60)); // 649 calendar_next_possible
-    resp.add_val(char.dice_game_next_free); // 650 dice_games_next_free
-    resp.add_val(char.dice_games_remaining); // 651 dice_games_remaining
-    resp.add_val(0); // 652
-    resp.add_val(0); // 653 druid mask
-    resp.add_val(0); // 654
-    resp.add_val(0); // 655
-    resp.add_val(0); // 656
-    resp.add_val(6); // 657
-    resp.add_val(0); // 658
-    resp.add_val(2); // 659
-    resp.add_val(0); // 660 pet dungeon timer
-    resp.add_val(0); // 661
-    resp.add_val(0); // 662
-    resp.add_val(0); // 663
-    resp.add_val(0); // 664
-    resp.add_val(0); // 665
-    resp.add_val(0); // 666
-    resp.add_val(0); // 667
-    resp.add_val(0); // 668
-    resp.add_val(0); // 669
-    resp.add_val(0); // 670
-    resp.add_val(1950020000000i64); // 671
-    resp.add_val(0); // 672
-    resp.add_val(0); // 673
-    resp.add_val(0); // 674
-    resp.add_val(0); // 675
-    resp.add_val(0); // 676
-    resp.add_val(0); // 677
-    resp.add_val(0); // 678
-    resp.add_val(0); // 679
-    resp.add_val(0); // 680
-    resp.add_val(0); // 681
-    resp.add_val(0); // 682
-    resp.add_val(0); // 683
-    resp.add_val(0); // 684
-    resp.add_val(0); // 685
-    resp.add_val(0); // 686
-    resp.add_val(0); // 687
-    resp.add_val(0); // 688
-    resp.add_val(0); // 689
-    resp.add_val(0); // 690
-    resp.add_val(0); // 691
-    resp.add_val(1); // 692
-    resp.add_val(0); // 693
-    resp.add_val(0); // 694
-    resp.add_val(0); // 695
-    resp.add_val(0); // 696
-    resp.add_val(0); // 697
-    resp.add_val(0); // 698
-    resp.add_val(0); // 699
-    resp.add_val(0); // 700
-    resp.add_val(0); // 701 bard instrument
-    resp.add_val(0); // 702
-    resp.add_val(0); // 703
-    resp.add_val(1); // 704
-    resp.add_val(0); // 705
-    resp.add_val(0); // 706
-    resp.add_val(0); // 707
-    resp.add_val(0); // 708
-    resp.add_val(0); // 709
-    resp.add_val(0); // 710
-    resp.add_val(0); // 711
-    resp.add_val(0); // 712
-    resp.add_val(0); // 713
-    resp.add_val(0); // 714
-    resp.add_val(0); // 715
-    resp.add_val(0); // 716
-    resp.add_val(0); // 717
-    resp.add_val(0); // 718
-    resp.add_val(0); // 719
-    resp.add_val(0); // 720
-    resp.add_val(0); // 721
-    resp.add_val(0); // 722
-    resp.add_val(0); // 723
-    resp.add_val(0); // 724
-    resp.add_val(0); // 725
-    resp.add_val(0); // 726
-    resp.add_val(0); // 727
-    resp.add_val(0); // 728
-    resp.add_val(0); // 729
-    resp.add_val(0); // 730
-    resp.add_val(0); // 731
-    resp.add_val(0); // 732
-    resp.add_val(0); // 733
-    resp.add_val(0); // 734
-    resp.add_val(0); // 735
-    resp.add_val(0); // 736
-    resp.add_val(0); // 737
-    resp.add_val(0); // 738
-    resp.add_val(0); // 739
-    resp.add_val(0); // 740
-    resp.add_val(0); // 741
-    resp.add_val(0); // 742
-    resp.add_val(0); // 743
-    resp.add_val(0); // 744
-    resp.add_val(0); // 745
-    resp.add_val(0); // 746
-    resp.add_val(0); // 747
-    resp.add_val(0); // 748
-    resp.add_val(0); // 749
-    resp.add_val(0); // 750
-    resp.add_val(0); // 751
-    resp.add_val(0); // 752
-    resp.add_val(0); // 753
-    resp.add_val(0); // 754
-    resp.add_val(0); // 755
-    resp.add_val(0); // 756
-    resp.add_val(0); // 757
-    resp.add_str(""); // 758
+    let quest_length = |length| mounted_quest_length(length, mount_effect);
+
+    let [q1item, q2item, q3item] =
+        load_items(db, [char.q1item, char.q2item, char.q3item]).await?;
+    let quests = [
+        QuestOffer {
+            flavour1: char.q1f1,
+            flavour2: char.q1f2,
+            monster: char.q1monster,
+            location: char.q1location,
+            length: quest_length(char.q1length),
+            item: q1item,
+            xp: char.q1xp,
+            silver: char.q1silver,
+        },
+        QuestOffer {
+            flavour1: char.q2f1,
+            flavour2: char.q2f2,
+            monster: char.q2monster,
+            location: char.q2location,
+            length: quest_length(char.q2length),
+            item: q2item,
+            xp: char.q2xp,
+            silver: char.q2silver,
+        },
+        QuestOffer {
+            flavour1: char.q3f1,
+            flavour2: char.q3f2,
+            monster: char.q3monster,
+            location: char.q3location,
+            length: quest_length(char.q3length),
+            item: q3item,
+            xp: char.q3xp,
+            silver: char.q3silver,
+        },
+    ];
+
+    let equipment = equipment_items(db, session.player_id).await?;
+    let attribute_bonus = equipment_bonus(db, session.player_id).await?;
+    let inventory = bag_items(db, session.player_id).await?;
+    let weapon_shop = shop_items(db, session.player_id, WEAPON_SHOP).await?;
+    let magic_shop = shop_items(db, session.player_id, MAGIC_SHOP).await?;
+
+    resp.add_section(&PlayerSave {
+        player_id: session.player_id,
+        level: char.level,
+        experience: char.experience,
+        next_level_xp: xp_for_next_level(char.level),
+        honor: char.honor,
+        rank: char.rank,
+        mushrooms_gained: char.mushrooms,
+        portrait: PortraitData {
+            mouth: char.mouth,
+            hair: char.hair,
+            brows: char.brows,
+            eyes: char.eyes,
+            beards: char.beards,
+            nose: char.nose,
+            ears: char.ears,
+            extra: char.extra,
+            horns: char.horns,
+            influencer: char.influencer,
+        },
+        race: char.race,
+        gender: char.gender,
+        class: char.class,
+        attributes,
+        attribute_bonus,
+        attributes_bought,
+        activity_typ: char.activitytyp,
+        activity_sub_type: char.activitysubtyp,
+        busy_until: char.busy_until,
+        equipment,
+        inventory,
+        tavern: Tavern {
+            quests,
+            thirst_for_adventure: char.tfa,
+            beer_drunk: char.beer_drunk,
+            dice_game_next_free: char.dice_game_next_free,
+            dice_games_remaining: char.dice_games_remaining,
+        },
+        mount,
+        mount_end,
+        weapon_shop,
+        magic_shop,
+        tutorial_status: char.tutorial_status,
+        arena_enemies: [1, 2, 3]
+            .map(|i| get_debug_value_default(&format!("arena_enemy{i}"), 1)),
+        arena_next_free: char.arena_next_free,
+        timestamp: now(),
+    });
 
     resp.add_key("resources");
     resp.add_val(session.player_id); // pid
@@ -689,9 +328,9 @@ pub(crate) async fn poll(
     resp.add_val(char.silver); // silver
     resp.add_val(0); // lucky coins
     resp.add_val(char.quicksand); // quicksand glasses
-    resp.add_val(0); // wood
+    resp.add_val(char.wood); // wood
     resp.add_val(0); // ??
-    resp.add_val(0); // stone
+    resp.add_val(char.stone); // stone
     resp.add_val(0); // ??
     resp.add_val(0); // metal
     resp.add_val(0); // arcane
@@ -809,11 +448,11 @@ pub(crate) async fn poll(
 
     resp.add_key("dailytasklist");
     resp.add_val(98);
-    for typ_id in 1..=10 {
-        resp.add_val(typ_id); // typ
-        resp.add_val(0); // current
-        resp.add_val(typ_id); // target
-        resp.add_val(10); // reward
+    for task in daily_tasks(db, session.player_id).await? {
+        resp.add_val(task.typ);
+        resp.add_val(task.progress);
+        resp.add_val(task.target);
+        resp.add_val(TASK_REWARD);
     }
 
     resp.add_key("eventtasklist");
@@ -0,0 +1,126 @@
+use super::{
+    in_seconds, now, update::poll, InRange, ServerError, ServerResponse,
+};
+use crate::{db::DbPool, request::Session};
+
+/// The activity type of working as a city guard. The sub type is the amount
+/// of hours
+pub(crate) const ACTIVITY_WORK: i64 = 1;
+
+/// The silver a character of the given level earns per hour of guard duty
+pub(crate) fn guard_wage(level: i64) -> i64 {
+    (level * 10 + 25) * 10
+}
+
+command_args! {
+    pub(crate) struct WorkStartArgs {
+        hours: InRange<1, 10>,
+    }
+}
+
+pub(crate) async fn player_work_start(
+    session: Session,
+    db: &DbPool,
+    args: WorkStartArgs,
+) -> Result<ServerResponse, ServerError> {
+    let hours = args.hours.0;
+    let mut tx = db.begin().await?;
+
+    let typ = sqlx::query_scalar!(
+        "SELECT typ FROM activity WHERE pid = $1", session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if typ != 0 {
+        return Err(ServerError::StillBusy);
+    }
+
+    // Unlike quests, mounts do not make the guard duty any shorter
+    sqlx::query!(
+        "UPDATE activity
+         SET typ = $2, sub_type = $3, started = $4, busy_until = $5
+         WHERE pid = $1",
+        session.player_id,
+        ACTIVITY_WORK,
+        hours,
+        now(),
+        in_seconds(hours * 60 * 60),
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
+
+pub(crate) async fn player_work_cancel(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    // Cancelling forfeits the whole wage. Work, that is already done, has to
+    // be collected with `PlayerWorkFinished` instead, so the wage is not lost
+    let res = sqlx::query!(
+        "UPDATE activity
+         SET typ = 0, sub_type = 0, started = 0, busy_until = 0
+         WHERE pid = $1 AND typ = $2 AND busy_until > $3",
+        session.player_id,
+        ACTIVITY_WORK,
+        now(),
+    )
+    .execute(db)
+    .await?;
+    if res.rows_affected() == 0 {
+        // We are not actually working, or are already done
+        return Err(ServerError::BadRequest);
+    }
+
+    poll(session, "", db, Default::default()).await
+}
+
+pub(crate) async fn player_work_finished(
+    session: Session,
+    db: &DbPool,
+    _args: (),
+) -> Result<ServerResponse, ServerError> {
+    let mut tx = db.begin().await?;
+
+    let row = sqlx::query!(
+        "SELECT typ, sub_type, busy_until, level
+         FROM activity NATURAL JOIN character
+         WHERE pid = $1",
+        session.player_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if row.typ != ACTIVITY_WORK {
+        // We are not actually working
+        return Err(ServerError::BadRequest);
+    }
+    if row.busy_until > now() {
+        return Err(ServerError::StillBusy);
+    }
+
+    sqlx::query!(
+        "UPDATE activity
+         SET typ = 0, sub_type = 0, started = 0, busy_until = 0
+         WHERE pid = $1",
+        session.player_id,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE character SET silver = silver + $2 WHERE pid = $1",
+        session.player_id,
+        guard_wage(row.level) * row.sub_type,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    poll(session, "", db, Default::default()).await
+}
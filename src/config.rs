@@ -0,0 +1,229 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// The config file we look for, if no explicit path has been provided
+pub const DEFAULT_CONFIG_PATH: &str = "sf-server.toml";
+
+/// Everything that can be changed about the server without rebuilding it.
+/// Values are resolved in the order: built in defaults, the TOML config file,
+/// environment variables and finally command line flags
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub capture: CaptureConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// The address both the HTTP and HTTPS listener bind to
+    pub bind_addr: IpAddr,
+    pub http_port: u16,
+    pub https_port: u16,
+    /// The domain the server is reachable under. Worlds are served on
+    /// subdomains of this (`w1.{domain}`), the default world on the domain
+    /// itself
+    pub domain: String,
+    /// The amount of seconds a session may be inactive, before the player
+    /// has to log in again
+    pub session_timeout: i64,
+    /// Whether or not logged in players can use cheat commands by whispering
+    /// them to "server". Only enable this on servers used for testing
+    pub cheats: bool,
+    /// The flags of the events, that are currently running. These are sent
+    /// to the client as `tavernspecialsub` (e.g. 0x1000 for the beer event)
+    pub events: i64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            http_port: 6767,
+            https_port: 6768,
+            domain: "localhost".to_string(),
+            session_timeout: 60 * 60,
+            cheats: false,
+            events: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// If this is set, we serve the game on the HTTPS port and redirect
+    /// everything on the HTTP port to it
+    pub enabled: bool,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cert: PathBuf::from("certs").join("localhost.crt"),
+            key: PathBuf::from("certs").join("localhost.key"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    /// Whether or not to apply pending migrations on startup
+    pub migrate: bool,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:sf.db".to_string(),
+            pool_size: 50,
+            migrate: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// If this is set, every command we handle is appended to this file,
+    /// together with the response, so that it can be replayed later on
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+#[command(about, version)]
+pub struct Args {
+    /// Path to the TOML config file. Defaults to `sf-server.toml` in the
+    /// working directory, if that exists
+    #[arg(short, long, env = "SF_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// The address to bind the HTTP(S) listeners to
+    #[arg(long, env = "SF_BIND_ADDR")]
+    pub bind_addr: Option<IpAddr>,
+    #[arg(long, env = "SF_HTTP_PORT")]
+    pub http_port: Option<u16>,
+    #[arg(long, env = "SF_HTTPS_PORT")]
+    pub https_port: Option<u16>,
+    /// The domain, whose subdomains identify the individual worlds
+    #[arg(long, env = "SF_DOMAIN")]
+    pub domain: Option<String>,
+    /// Allow cheat commands (whispers to "server")
+    #[arg(long, env = "SF_CHEATS")]
+    pub cheats: bool,
+    /// Whether or not to serve the game via HTTPS
+    #[arg(long, env = "SF_TLS")]
+    pub tls: Option<bool>,
+    #[arg(long, env = "SF_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    #[arg(long, env = "SF_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+    /// The maximum amount of concurrent database connections
+    #[arg(long, env = "SF_DB_POOL_SIZE")]
+    pub db_pool_size: Option<u32>,
+    /// Do not apply pending migrations on startup
+    #[arg(long, env = "SF_NO_MIGRATE")]
+    pub no_migrate: bool,
+    /// Record all commands and responses to this JSONL file
+    #[arg(long, env = "SF_CAPTURE")]
+    pub capture: Option<PathBuf>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read config file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid config file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl Config {
+    /// Reads the config file referenced by the args (if any) and applies all
+    /// env/command line overrides on top of it
+    pub fn load(args: &Args) -> Result<Config, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Config::default(),
+        };
+        config.apply_args(args);
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    fn apply_args(&mut self, args: &Args) {
+        if let Some(addr) = args.bind_addr {
+            self.server.bind_addr = addr;
+        }
+        if let Some(port) = args.http_port {
+            self.server.http_port = port;
+        }
+        if let Some(port) = args.https_port {
+            self.server.https_port = port;
+        }
+        if let Some(domain) = &args.domain {
+            self.server.domain.clone_from(domain);
+        }
+        if args.cheats {
+            self.server.cheats = true;
+        }
+        if let Some(enabled) = args.tls {
+            self.tls.enabled = enabled;
+        }
+        if let Some(cert) = &args.tls_cert {
+            self.tls.cert.clone_from(cert);
+        }
+        if let Some(key) = &args.tls_key {
+            self.tls.key.clone_from(key);
+        }
+        if let Some(url) = &args.database_url {
+            self.database.url.clone_from(url);
+        }
+        if let Some(size) = args.db_pool_size {
+            self.database.pool_size = size;
+        }
+        if args.no_migrate {
+            self.database.migrate = false;
+        }
+        if let Some(file) = &args.capture {
+            self.capture.file = Some(file.clone());
+        }
+    }
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Sets the global config. This has to happen before anything calls
+/// `get_config()`, otherwise the defaults will be locked in
+pub fn init_config(config: Config) -> Result<(), Config> {
+    CONFIG.set(config)
+}
+
+pub fn get_config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
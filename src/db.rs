@@ -0,0 +1,149 @@
+use std::str::FromStr;
+
+use log::info;
+#[cfg(feature = "postgres")]
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+#[cfg(not(feature = "postgres"))]
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions,
+};
+use sqlx::{
+    migrate::{MigrateDatabase, MigrateError, Migrator},
+    pool::PoolOptions,
+    Pool,
+};
+use thiserror::Error;
+
+use crate::config::DatabaseConfig;
+
+/// The database we store everything in. SQLite by default, Postgres if the
+/// `postgres` feature is enabled. Note that the query macros check against
+/// the database in `DATABASE_URL`, so that has to be of the same kind
+#[cfg(not(feature = "postgres"))]
+pub type Backend = sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+pub type Backend = sqlx::Postgres;
+
+pub type DbPool = Pool<Backend>;
+
+/// The migrations for the backend we have been built for. Schema changes
+/// have to be made to both `migrations/` and `migrations/postgres/`
+#[cfg(not(feature = "postgres"))]
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+#[cfg(feature = "postgres")]
+pub static MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+#[derive(Debug, Error)]
+pub enum DbSetupError {
+    #[error("invalid database url {0}: {1}")]
+    InvalidUrl(String, sqlx::Error),
+    #[error("could not create database {0}: {1}")]
+    Create(String, sqlx::Error),
+    #[error("could not open database {0}: {1}")]
+    Connect(String, sqlx::Error),
+    #[error(
+        "the schema of database {0} does not match this version of the \
+         server ({1}). Restore a backup, or start with a fresh database"
+    )]
+    SchemaMismatch(String, MigrateError),
+    #[error("could not migrate database {0}: {1}")]
+    Migrate(String, MigrateError),
+}
+
+/// Opens the database at the configured url, creating it if it does not yet
+/// exist, and brings its schema up to date (unless disabled)
+pub async fn connect_db(
+    config: &DatabaseConfig,
+) -> Result<DbPool, DbSetupError> {
+    let url = &config.url;
+    let options = connect_options(url)
+        .map_err(|e| DbSetupError::InvalidUrl(url.clone(), e))?;
+
+    let exists = Backend::database_exists(url)
+        .await
+        .map_err(|e| DbSetupError::Create(url.clone(), e))?;
+    if !exists {
+        info!("Creating database {url}");
+        Backend::create_database(url)
+            .await
+            .map_err(|e| DbSetupError::Create(url.clone(), e))?;
+    }
+
+    let db = PoolOptions::<Backend>::new()
+        .max_connections(config.pool_size)
+        .connect_with(options)
+        .await
+        .map_err(|e| DbSetupError::Connect(url.clone(), e))?;
+
+    if config.migrate {
+        MIGRATOR.run(&db).await.map_err(|e| match e {
+            MigrateError::VersionMissing(_)
+            | MigrateError::VersionMismatch(_)
+            | MigrateError::Dirty(_) => {
+                DbSetupError::SchemaMismatch(url.clone(), e)
+            }
+            e => DbSetupError::Migrate(url.clone(), e),
+        })?;
+        info!("Database schema of {url} is up to date");
+    }
+    Ok(db)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn connect_options(url: &str) -> Result<SqliteConnectOptions, sqlx::Error> {
+    Ok(SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true)
+        .foreign_keys(true)
+        .journal_mode(SqliteJournalMode::Wal))
+}
+
+#[cfg(feature = "postgres")]
+fn connect_options(url: &str) -> Result<PgConnectOptions, sqlx::Error> {
+    PgConnectOptions::from_str(url)
+}
+
+/// Creates an empty, fully migrated in-memory database for tests
+#[cfg(not(feature = "postgres"))]
+pub async fn test_db() -> DbPool {
+    // Use the same options as the real db, so that things like foreign keys
+    // are enforced in tests too
+    let options =
+        connect_options("sqlite::memory:").expect("invalid test db url");
+    // Every connection to an in-memory db gets its own db, so we have to
+    // make sure there is only ever exactly one
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .expect("could not create test db");
+    MIGRATOR.run(&db).await.expect("could not migrate test db");
+    db
+}
+
+/// Creates an empty, fully migrated database for tests on the Postgres
+/// server in `SF_TEST_POSTGRES` (a local one by default). The databases are
+/// never dropped, so the server should be a throwaway one
+#[cfg(feature = "postgres")]
+pub async fn test_db() -> DbPool {
+    let server = std::env::var("SF_TEST_POSTGRES")
+        .unwrap_or_else(|_| "postgres://postgres@localhost".to_string());
+    let url = format!(
+        "{}/sf_test_{}_{}",
+        server.trim_end_matches('/'),
+        std::process::id(),
+        fastrand::u32(..)
+    );
+    Backend::create_database(&url)
+        .await
+        .expect("could not create test db");
+    let options = connect_options(&url).expect("invalid test db url");
+    let db = PgPoolOptions::new()
+        .max_connections(4)
+        .connect_with(options)
+        .await
+        .expect("could not connect to test db");
+    MIGRATOR.run(&db).await.expect("could not migrate test db");
+    db
+}
@@ -1,17 +1,28 @@
 use std::collections::HashMap;
 
-use axum::{extract::Request, response::*};
+use axum::{
+    extract::{Request, State},
+    response::*,
+};
 use log::error;
 use once_cell::sync::OnceCell;
-use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use reqwest::{
+    header::{CONTENT_TYPE, HOST},
+    Client, StatusCode,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::db::DbPool;
+
 /// In order to provide the S&F interface without actually hosting and thus
 /// infringing on their copyrighted material, we just forward requests to our
 /// server to theirs. Since CORS is annoying, we must manually fetch some of
 /// the config stuff. In addition, we must modify some of them, to fix
 /// issues around the otherwise invalid server domain
-pub async fn forward(req: Request) -> Result<Response, StatusCode> {
+pub async fn forward(
+    State(db): State<DbPool>,
+    req: Request,
+) -> Result<Response, StatusCode> {
     let uri = req
         .uri()
         .path_and_query()
@@ -80,8 +91,6 @@ pub async fn forward(req: Request) -> Result<Response, StatusCode> {
 
         config.servers.clear();
 
-        let db = crate::get_db().await.map_err(|_| INTERNAL_ERR)?;
-
         let servers = sqlx::query!("SELECT * FROM world")
             .fetch_all(&db)
             .await
@@ -90,12 +99,16 @@ pub async fn forward(req: Request) -> Result<Response, StatusCode> {
                 INTERNAL_ERR
             })?;
 
+        // Requests usually only contain the path, so the host we have been
+        // reached under has to be taken from the header
+        let server_host = req
+            .headers()
+            .get(HOST)
+            .and_then(|a| a.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()))
+            .ok_or(INTERNAL_ERR)?;
+
         for server in servers {
-            let server_host = req
-                .uri()
-                .authority()
-                .map(|a| a.as_str())
-                .ok_or(INTERNAL_ERR)?;
             let server_url = if !server.ident.is_empty() {
                 format!("{}.{server_host}", server.ident)
             } else {
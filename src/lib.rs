@@ -0,0 +1,39 @@
+use axum::{http::Method, routing::get, Router};
+use db::DbPool;
+use request::{handle_cmd, handle_req};
+
+use crate::response::*;
+
+pub mod capture;
+pub mod command;
+pub mod config;
+pub mod db;
+pub mod frontend;
+pub mod misc;
+pub mod request;
+pub mod response;
+pub mod scheduler;
+#[cfg(test)]
+mod replay;
+
+pub const DEFAULT_CRYPTO_ID: &str = "0-00000000000000";
+pub const DEFAULT_SESSION_ID: &str = "00000000000000000000000000000000";
+pub const DEFAULT_CRYPTO_KEY: &str = "[_/$VV&*Qg&)r?~g";
+pub const SERVER_VERSION: u32 = 2008;
+
+/// Builds the router for everything the game client talks to. All handlers
+/// use the provided db
+pub fn build_router(db: DbPool) -> Router {
+    let cors = tower_http::cors::CorsLayer::new()
+        .allow_headers(tower_http::cors::Any)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_origin(tower_http::cors::Any);
+
+    Router::new()
+        .route("/cmd.php", get(handle_cmd))
+        .route("/req.php", get(handle_req))
+        .route("/*key", get(frontend::forward))
+        .route("/", get(frontend::forward))
+        .layer(cors)
+        .with_state(db)
+}
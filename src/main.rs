@@ -1,73 +1,75 @@
-use std::{net::SocketAddr, path::PathBuf};
+use std::net::SocketAddr;
 
-use axum::{
-    extract::Host,
-    http::{Method, Uri},
-    response::Redirect,
-    routing::get,
-};
+use axum::{extract::Host, http::Uri, response::Redirect};
+use clap::Parser;
 use log::{debug, error, info, warn};
-use request::{handle_cmd, handle_req};
-use sqlx::{sqlite::SqlitePoolOptions, Sqlite};
-
-use crate::response::*;
-
-pub mod command;
-pub mod frontend;
-pub mod misc;
-pub mod request;
-pub mod response;
+use sf_server::{
+    build_router,
+    config::{get_config, init_config, Args, Config},
+    command::repair_quests,
+    db::connect_db,
+    scheduler::spawn_scheduler,
+};
 
 #[tokio::main]
 async fn main() {
     // initialize tracing
     tracing_subscriber::fmt::init();
-    let cors = tower_http::cors::CorsLayer::new()
-        .allow_headers(tower_http::cors::Any)
-        .allow_methods([Method::GET, Method::POST])
-        .allow_origin(tower_http::cors::Any);
 
-    let app = axum::Router::new()
-        .route("/cmd.php", get(handle_cmd))
-        .route("/req.php", get(handle_req))
-        .route("/*key", get(frontend::forward))
-        .route("/", get(frontend::forward))
-        .layer(cors);
+    let args = Args::parse();
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    init_config(config).expect("config already initialized");
+    let config = get_config();
+
+    let db = match connect_db(&config.database).await {
+        Ok(db) => db,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = repair_quests(&db).await {
+        error!("Could not check the quests of all characters: {e}");
+    }
+    spawn_scheduler(db.clone());
+    let app = build_router(db);
 
-    if !PROVIDE_HTTPS {
-        let addr = SocketAddr::from(([127, 0, 0, 1], HTTP_PORT));
+    let server = &config.server;
+    if !config.tls.enabled {
+        let addr = SocketAddr::new(server.bind_addr, server.http_port);
         let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        info!("listening on http://{}", addr);
         axum::serve(listener, app).await.unwrap();
     } else {
         tokio::spawn(redirect_http_to_https());
 
         use axum_server::tls_rustls::RustlsConfig;
-        let config = RustlsConfig::from_pem_file(
-            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                .join("certs")
-                .join("localhost.crt"),
-            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                .join("certs")
-                .join("localhost.key"),
-        )
-        .await
-        .unwrap();
-
-        let addr = SocketAddr::from(([127, 0, 0, 1], HTTPS_PORT));
+        let tls_config =
+            RustlsConfig::from_pem_file(&config.tls.cert, &config.tls.key)
+                .await
+                .unwrap_or_else(|e| {
+                    error!(
+                        "Could not load TLS cert {:?} / key {:?}: {e}",
+                        config.tls.cert, config.tls.key
+                    );
+                    std::process::exit(1);
+                });
+
+        let addr = SocketAddr::new(server.bind_addr, server.https_port);
         info!("listening on https://{}", addr);
-        axum_server::bind_rustls(addr, config)
+        axum_server::bind_rustls(addr, tls_config)
             .serve(app.into_make_service())
             .await
             .unwrap()
     }
 }
 
-// TODO: Make thise config, or env variables
-static PROVIDE_HTTPS: bool = true;
-static HTTP_PORT: u16 = 6767;
-static HTTPS_PORT: u16 = 6768;
-
-#[allow(dead_code)]
 async fn redirect_http_to_https() {
     fn make_https(host: String, uri: Uri) -> Result<Uri, axum::BoxError> {
         let mut parts = uri.into_parts();
@@ -78,8 +80,11 @@ async fn redirect_http_to_https() {
             parts.path_and_query = Some("/".parse().unwrap());
         }
 
-        let https_host =
-            host.replace(&HTTP_PORT.to_string(), &HTTPS_PORT.to_string());
+        let server = &get_config().server;
+        let https_host = host.replace(
+            &server.http_port.to_string(),
+            &server.https_port.to_string(),
+        );
         parts.authority = Some(https_host.parse()?);
 
         Ok(Uri::from_parts(parts)?)
@@ -95,7 +100,8 @@ async fn redirect_http_to_https() {
         }
     };
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], HTTP_PORT));
+    let server = &get_config().server;
+    let addr = SocketAddr::new(server.bind_addr, server.http_port);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     debug!("listening on {}", listener.local_addr().unwrap());
     use axum::handler::HandlerWithoutStateExt;
@@ -103,25 +109,3 @@ async fn redirect_http_to_https() {
         .await
         .unwrap();
 }
-
-pub const DEFAULT_CRYPTO_ID: &str = "0-00000000000000";
-pub const DEFAULT_SESSION_ID: &str = "00000000000000000000000000000000";
-pub const DEFAULT_CRYPTO_KEY: &str = "[_/$VV&*Qg&)r?~g";
-pub const SERVER_VERSION: u32 = 2008;
-
-pub async fn get_db() -> Result<sqlx::Pool<Sqlite>, ServerError> {
-    use async_once_cell::OnceCell;
-    static DB: OnceCell<sqlx::Pool<Sqlite>> = OnceCell::new();
-    DB.get_or_try_init(async {
-        SqlitePoolOptions::new()
-            .max_connections(50)
-            .connect(env!("DATABASE_URL"))
-            .await
-            .map_err(|e| {
-                error!("Database connection error: {:?}", e);
-                e.into()
-            })
-    })
-    .await
-    .cloned()
-}
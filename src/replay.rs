@@ -0,0 +1,278 @@
+//! Replays captured commands (see `capture`) against a fresh database and
+//! compares our responses with the recorded ones. Recordings have to start
+//! with an empty database, otherwise the pids will not line up
+
+use std::path::Path;
+
+use crate::{
+    capture::{response_body, CapturedCommand},
+    command::{handle_command, CommandArguments},
+    db::DbPool,
+    request::Session,
+    ServerError,
+};
+
+/// Keys, whose values are different on every run
+const VOLATILE_KEYS: &[&str] =
+    &["sessionid", "cryptoid", "cryptokey", "timestamp"];
+
+/// A value in a response, that is different from the recording
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The line of the command in the capture file
+    pub line: usize,
+    pub command: String,
+    pub key: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {} ({}) {}: expected {:?}, got {:?}",
+            self.line, self.command, self.key, self.expected, self.actual
+        )
+    }
+}
+
+pub fn read_capture(path: &Path) -> Vec<CapturedCommand> {
+    let text = std::fs::read_to_string(path).expect("could not read capture");
+    text.lines()
+        .filter(|a| !a.trim().is_empty())
+        .map(|a| serde_json::from_str(a).expect("invalid captured command"))
+        .collect()
+}
+
+/// Runs all commands against the db and returns everything, that does not
+/// match the recorded responses
+pub async fn replay(
+    db: &DbPool,
+    commands: &[CapturedCommand],
+) -> Result<Vec<Mismatch>, ServerError> {
+    let mut mismatches = vec![];
+    for (idx, captured) in commands.iter().enumerate() {
+        let session = session_for(db, captured).await?;
+        fastrand::seed(captured.seed);
+        let args = CommandArguments(
+            captured.args.iter().map(String::as_str).collect(),
+        );
+        let res = handle_command(db, &captured.command, args, session).await;
+        let actual = response_body(&res);
+        mismatches.extend(diff(idx + 1, captured, &actual));
+    }
+    Ok(mismatches)
+}
+
+/// Formats the mismatches for a failed assertion
+pub fn report(mismatches: &[Mismatch]) -> String {
+    mismatches
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Recreates the session, that the command has been sent with
+async fn session_for(
+    db: &DbPool,
+    captured: &CapturedCommand,
+) -> Result<Session, ServerError> {
+    let unauthed = Session::new_unauthed(captured.world_id);
+    if captured.pid <= 0 {
+        return Ok(unauthed);
+    }
+
+    let row = sqlx::query!(
+        "SELECT session_id, crypto_id, crypto_key, login_count
+         FROM session
+         JOIN character ON character.pid = session.pid
+         WHERE session.pid = $1",
+        captured.pid
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(match row {
+        Some(row) => Session {
+            player_id: captured.pid,
+            world_id: captured.world_id,
+            session_id: row.session_id,
+            crypto_id: row.crypto_id,
+            crypto_key: row.crypto_key,
+            login_count: row.login_count,
+        },
+        // The recording used a session, that we never created. Just act as
+        // if it existed and let the command fail, if it has to
+        None => Session {
+            player_id: captured.pid,
+            ..unauthed
+        },
+    })
+}
+
+fn diff(
+    line: usize,
+    captured: &CapturedCommand,
+    actual: &str,
+) -> Vec<Mismatch> {
+    let expected = parse_response(&captured.response);
+    let actual = parse_response(actual);
+
+    let mut mismatches = vec![];
+    for idx in 0..expected.len().max(actual.len()) {
+        let expected = expected.get(idx);
+        let actual = actual.get(idx);
+        if expected == actual {
+            continue;
+        }
+        let key = expected.or(actual).map(|a| a.0).unwrap_or_default();
+        mismatches.push(Mismatch {
+            line,
+            command: captured.command.clone(),
+            key: key.to_string(),
+            expected: expected.map(|a| a.1.clone()),
+            actual: actual.map(|a| a.1.clone()),
+        });
+    }
+    mismatches
+}
+
+/// Splits the response into its keys and normalized values
+fn parse_response(body: &str) -> Vec<(&str, String)> {
+    body.split('&')
+        .filter(|a| !a.is_empty())
+        .map(|part| {
+            let (key, val) = part.split_once(':').unwrap_or((part, ""));
+            (key, normalize(key, val))
+        })
+        .collect()
+}
+
+/// Replaces everything, that depends on when the command was run
+fn normalize(key: &str, val: &str) -> String {
+    if VOLATILE_KEYS.contains(&key) {
+        return "<volatile>".to_string();
+    }
+    val.split('/')
+        .map(|a| match a.parse::<i64>() {
+            Ok(num) if (1_000_000_000..10_000_000_000).contains(&num) => {
+                "<time>"
+            }
+            _ => a,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use sf_api::misc::{sha1_hash, HASH_CONST};
+
+    use super::*;
+    use crate::{capture::redacted_args, db::test_db};
+
+    /// Runs the commands the same way, as they would be captured
+    async fn record(
+        db: &DbPool,
+        script: &[(&str, String, i64)],
+    ) -> Vec<CapturedCommand> {
+        let mut res = vec![];
+        for (idx, (command, args, pid)) in script.iter().enumerate() {
+            let split: Vec<_> = args.split('/').collect();
+            let mut captured = CapturedCommand {
+                command: command.to_string(),
+                args: redacted_args(command, &split),
+                pid: *pid,
+                world_id: 1,
+                seed: idx as u64,
+                response: String::new(),
+            };
+            let session = session_for(db, &captured).await.unwrap();
+            fastrand::seed(captured.seed);
+            let resp =
+                handle_command(db, command, CommandArguments(split), session)
+                    .await;
+            captured.response = response_body(&resp);
+            res.push(captured);
+        }
+        res
+    }
+
+    fn login_hash(password: &str, login_count: i64) -> String {
+        let pw_hash = sha1_hash(&format!("{password}{HASH_CONST}"));
+        sha1_hash(&format!("{pw_hash}{login_count}"))
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let portrait = "1,1,1,1,1,1,1,1,1";
+        let script = [
+            (
+                "AccountCreate",
+                format!("Alice/secret123/alice@example.com/1/1/1/{portrait}"),
+                -1,
+            ),
+            (
+                "AccountCreate",
+                format!("Bob/secret123/bob@example.com/2/2/2/{portrait}"),
+                -1,
+            ),
+            (
+                "AccountLogin",
+                format!("Alice/{}/1", login_hash("secret123", 1)),
+                -1,
+            ),
+            ("Poll", String::new(), 1),
+            ("PlayerAdventureStart", "1/0".to_string(), 1),
+            ("PlayerLookAt", "Bob".to_string(), 1),
+            ("PlayerArenaFight", "Bob".to_string(), 1),
+            ("Poll", String::new(), 1),
+        ];
+
+        let recording = record(&test_db().await, &script).await;
+        assert!(
+            recording.iter().all(|a| !a.response.starts_with("error:")),
+            "{recording:#?}"
+        );
+        let args = recording.iter().flat_map(|a| &a.args);
+        assert!(
+            args.clone().all(|a| !a.contains("secret123")
+                && !a.contains("example.com")
+                && *a != login_hash("secret123", 1)),
+            "{recording:#?}"
+        );
+
+        let mismatches = replay(&test_db().await, &recording).await.unwrap();
+        assert!(mismatches.is_empty(), "{}", report(&mismatches));
+    }
+
+    /// Replays every capture in `tests/replays`
+    #[tokio::test]
+    async fn recorded_captures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/replays");
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|a| a.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let commands = read_capture(&path);
+            let mismatches = replay(&test_db().await, &commands).await.unwrap();
+            assert!(
+                mismatches.is_empty(),
+                "{path:?}:\n{}",
+                report(&mismatches)
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_times() {
+        assert_eq!(normalize("a", "1/1708336503/x"), "1/<time>/x");
+        assert_eq!(normalize("sessionid", "abc"), "<volatile>");
+    }
+}
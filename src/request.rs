@@ -1,21 +1,26 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr};
 
-use axum::{extract::Query, response::Response};
+use axum::{
+    extract::{Host, Query, State},
+    response::Response,
+};
 use base64::Engine;
-use log::error;
+use log::{error, warn};
 use sf_api::misc::decrypt_server_request;
 
 use crate::{
-    command::{handle_command, CommandArguments},
-    get_db,
+    command::{find_command, handle_command, now, CommandArguments},
+    config::get_config,
+    db::DbPool,
     misc::OptionGet,
     ServerError, DEFAULT_CRYPTO_ID, DEFAULT_CRYPTO_KEY, DEFAULT_SESSION_ID,
 };
 
 pub async fn handle_cmd(
+    State(db): State<DbPool>,
+    Host(host): Host,
     req_params: Query<HashMap<String, String>>,
 ) -> Result<Response, Response> {
-    let db = get_db().await?;
     let command_name = req_params.get("req").get("request")?.as_str();
     let crypto_id = req_params.get("sid").get("crypto_id")?;
     let command_args = req_params.get("params").get("command_args")?;
@@ -30,56 +35,15 @@ pub async fn handle_cmd(
         ServerError::BadRequest
     })?;
 
-    let world = "";
-
-    let session = match crypto_id == DEFAULT_CRYPTO_ID {
-        true => {
-            let world_id = sqlx::query_scalar!(
-                "SELECT world_id
-                     FROM world
-                     WHERE ident = $1",
-                world
-            )
-            .fetch_one(&db)
-            .await
-            .map_err(|e| {
-                error!("Database error while fetching world_id: {:?}", e);
-                ServerError::DBError(e)
-            })?;
-
-            Session::new_unauthed(world_id)
-        }
-        false => {
-            let res = sqlx::query!(
-                "SELECT character.pid, crypto_key, session_id, crypto_id, \
-                 world_id, login_count
-                 FROM character
-                 NATURAL JOIN session
-                 NATURAL JOIN world
-                 WHERE crypto_id = $1 AND world.ident = $2",
-                crypto_id,
-                world
-            )
-            .fetch_optional(&db)
-            .await
-            .map_err(|e| {
-                error!("Database error while fetching session: {:?}", e);
-                ServerError::DBError(e)
-            })?;
-
-            let Some(row) = res else {
-                return Err(ServerError::InvalidAuth.into());
-            };
-            Session {
-                player_id: row.pid,
-                world_id: row.world_id,
-                session_id: row.session_id,
-                crypto_id: row.crypto_id,
-                crypto_key: row.crypto_key,
-                login_count: row.login_count,
-            }
-        }
-    };
+    // Commands sent via cmd.php are not encrypted and carry neither a
+    // session id, nor a login count, so they can only be used to log in
+    if find_command(command_name).is_some_and(|a| a.needs_auth) {
+        warn!("{command_name} sent unencrypted for {crypto_id}");
+        return Err(ServerError::InvalidAuth.into());
+    }
+
+    let world_id = resolve_world(&db, &host).await?;
+    let session = Session::new_unauthed(world_id);
     let args = CommandArguments(command_args.split('/').collect());
 
     handle_command(&db, command_name, args, session)
@@ -89,10 +53,11 @@ pub async fn handle_cmd(
 }
 
 pub async fn handle_req(
+    State(db): State<DbPool>,
+    Host(host): Host,
     req: Query<HashMap<String, String>>,
 ) -> Result<Response, Response> {
     let request = req.get("req").get("request parameter")?;
-    let db = get_db().await?;
 
     if request.len() < DEFAULT_CRYPTO_ID.len() + 5 {
         Err(ServerError::BadRequest)?;
@@ -105,66 +70,29 @@ pub async fn handle_req(
         Err(ServerError::BadRequest)?;
     }
 
-    // TODO: Parse from request url
-    let world = "";
-
-    let session = match crypto_id == DEFAULT_CRYPTO_ID {
-        true => {
-            let world_id = sqlx::query_scalar!(
-                "SELECT world_id
-                     FROM world
-                     WHERE ident = $1",
-                world
-            )
-            .fetch_one(&db)
-            .await
-            .map_err(|e| {
-                error!("Database error while fetching world_id: {:?}", e);
-                ServerError::DBError(e)
-            })?;
-
-            Session::new_unauthed(world_id)
-        }
-        false => {
-            let res = sqlx::query!(
-                "SELECT character.pid, crypto_key, session_id, crypto_id, \
-                 world_id, login_count
-                     FROM character
-                     NATURAL JOIN session
-                     NATURAL JOIN world
-                     WHERE crypto_id = $1 AND world.ident = $2",
-                crypto_id,
-                world
-            )
-            .fetch_optional(&db)
-            .await
-            .map_err(|e| {
-                error!("Database error while fetching session: {:?}", e);
-                ServerError::DBError(e)
-            })?;
-
-            let Some(row) = res else {
-                return Err(ServerError::InvalidAuth.into());
-            };
-            Session {
-                player_id: row.pid,
-                world_id: row.world_id,
-                session_id: row.session_id,
-                crypto_id: row.crypto_id,
-                crypto_key: row.crypto_key,
-                login_count: row.login_count,
-            }
-        }
-    };
+    let world_id = resolve_world(&db, &host).await?;
+    let mut session = load_session(&db, world_id, crypto_id).await?;
 
     let request =
         decrypt_server_request(encrypted_request, &session.crypto_key)
             .map_err(|_| ServerError::BadRequest)?;
 
-    let Some((_session_id, request)) = request.split_once('|') else {
+    let Some((session_id, request)) = request.split_once('|') else {
         return Err(ServerError::BadRequest.into());
     };
 
+    if session.is_authed() {
+        if session_id != session.session_id {
+            warn!("Invalid session id for {}", session.player_id);
+            return Err(ServerError::InvalidAuth.into());
+        }
+        let login_count = req
+            .get("c")
+            .and_then(|a| a.parse::<i64>().ok())
+            .get("login count")?;
+        use_login_count(&db, &mut session, login_count).await?;
+    }
+
     let request = request.trim_matches('|');
 
     let Some((command_name, command_args)) = request.split_once(':') else {
@@ -178,6 +106,141 @@ pub async fn handle_req(
         .map(|a| a.into())
 }
 
+/// Extracts the ident of the world a request is meant for from the host it
+/// was sent to. Worlds are served on subdomains of the configured domain,
+/// the default world (ident "") on the domain itself, or if the server is
+/// accessed directly by its IP
+pub fn world_ident<'a>(host: &'a str, domain: &str) -> Option<&'a str> {
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|a| a.is_ascii_digit()) => {
+            host
+        }
+        _ => host,
+    };
+
+    if host.eq_ignore_ascii_case(domain)
+        || host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok()
+    {
+        return Some("");
+    }
+
+    let split = host.len().checked_sub(domain.len())?;
+    let (ident, base) = (host.get(..split)?, host.get(split..)?);
+    if !base.eq_ignore_ascii_case(domain) {
+        return None;
+    }
+    ident.strip_suffix('.').filter(|a| !a.is_empty() && !a.contains('.'))
+}
+
+/// Figures out the world_id of the world the request was sent to
+async fn resolve_world(db: &DbPool, host: &str) -> Result<i64, ServerError> {
+    let Some(world) = world_ident(host, &get_config().server.domain) else {
+        warn!("Request for unknown host: {host}");
+        return Err(ServerError::UnknownWorld);
+    };
+
+    let world_id = sqlx::query_scalar!(
+        "SELECT world_id
+             FROM world
+             WHERE ident = $1",
+        world
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        error!("Database error while fetching world_id: {:?}", e);
+        ServerError::DBError(e)
+    })?;
+
+    world_id.ok_or_else(|| {
+        warn!("Request for unknown world: {world}");
+        ServerError::UnknownWorld
+    })
+}
+
+/// Looks up the session belonging to the provided crypto id in the given
+/// world. The default crypto id is used by clients, that are not logged in
+async fn load_session(
+    db: &DbPool,
+    world_id: i64,
+    crypto_id: &str,
+) -> Result<Session, ServerError> {
+    if crypto_id == DEFAULT_CRYPTO_ID {
+        return Ok(Session::new_unauthed(world_id));
+    }
+
+    let res = sqlx::query!(
+        "SELECT character.pid, crypto_key, session_id, crypto_id, world_id, \
+         login_count, last_active
+         FROM character
+         JOIN session ON session.pid = character.pid
+         WHERE crypto_id = $1 AND world_id = $2",
+        crypto_id,
+        world_id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        error!("Database error while fetching session: {:?}", e);
+        ServerError::DBError(e)
+    })?;
+
+    let Some(row) = res else {
+        return Err(ServerError::InvalidAuth);
+    };
+
+    let now = now();
+    if row.last_active + get_config().server.session_timeout < now {
+        sqlx::query!("DELETE FROM session WHERE crypto_id = $1", crypto_id)
+            .execute(db)
+            .await?;
+        return Err(ServerError::SessionExpired);
+    }
+
+    sqlx::query!(
+        "UPDATE session SET last_active = $1 WHERE crypto_id = $2", now,
+        crypto_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(Session {
+        player_id: row.pid,
+        world_id: row.world_id,
+        session_id: row.session_id,
+        crypto_id: row.crypto_id,
+        crypto_key: row.crypto_key,
+        login_count: row.login_count,
+    })
+}
+
+/// Every request of a logged in client has to carry a higher login count,
+/// than the one before, so that requests can not be replayed
+async fn use_login_count(
+    db: &DbPool,
+    session: &mut Session,
+    login_count: i64,
+) -> Result<(), ServerError> {
+    let res = sqlx::query!(
+        "UPDATE session SET login_count = $1
+         WHERE crypto_id = $2 AND login_count < $1",
+        login_count,
+        session.crypto_id
+    )
+    .execute(db)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        warn!(
+            "Replayed request for {} (login count {login_count})",
+            session.player_id
+        );
+        return Err(ServerError::LoginCountTooLow);
+    }
+    session.login_count = login_count;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct Session {
     pub player_id: i64,
@@ -200,13 +263,8 @@ impl Session {
         }
     }
 
-    pub fn can_request(&self, command: &str) -> bool {
+    /// Whether or not this session belongs to a logged in character
+    pub fn is_authed(&self) -> bool {
         self.player_id > 0
-            || [
-                "AccountCreate", "AccountLogin", "AccountCheck",
-                "AccountDelete", "PlayerHelpshiftAuthtoken",
-                "getserverversion", "PlayerWhisper",
-            ]
-            .contains(&command)
     }
 }
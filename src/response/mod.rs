@@ -0,0 +1,234 @@
+use std::fmt::Write;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use log::{debug, error};
+pub use section::*;
+use thiserror::Error;
+
+pub mod section;
+
+pub enum ServerResponse {
+    Success,
+    Data(String),
+}
+
+impl ServerResponse {
+    /// The body we send to the client
+    pub fn body(&self) -> &str {
+        match self {
+            ServerResponse::Success => "Success:",
+            ServerResponse::Data(data) => data,
+        }
+    }
+}
+
+/// Everything that can go wrong while handling a request. The `Display`
+/// impl is meant for our logs and may contain internal details. What the
+/// client gets to see is decided by `ServerError::client_error()`
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("name is not available")]
+    InvalidName,
+    #[error("character exists")]
+    CharacterExists,
+    #[error("mail is not available")]
+    MailTaken,
+    #[error("invalid mail")]
+    InvalidMail,
+    #[error("password too short")]
+    PasswordTooShort,
+    #[error("bad request")]
+    BadRequest,
+    #[error("wrong pass")]
+    WrongPassword,
+    #[error("command requires valid session")]
+    InvalidAuth,
+    #[error("session expired")]
+    SessionExpired,
+    #[error("login count is too low (replayed request?)")]
+    LoginCountTooLow,
+    #[error("unknown world")]
+    UnknownWorld,
+    #[error("unknown request: {0}")]
+    UnknownRequest(Box<str>),
+    #[error("command missing argument: {0}")]
+    MissingArgument(&'static str),
+    #[error("command has invalid argument: {0}")]
+    InvalidArgument(&'static str),
+    #[error("player not found")]
+    PlayerNotFound,
+    #[error("need more gold")]
+    NotEnoughMoney,
+    #[error("need more mushrooms")]
+    NotEnoughMushrooms,
+    #[error("need more thirst for adventure")]
+    NotEnoughThirst,
+    #[error("daily beer limit reached")]
+    TooMuchBeer,
+    #[error("need more hourglasses")]
+    NotEnoughHourglasses,
+    #[error("inventory full")]
+    InventoryFull,
+    #[error("still busy")]
+    StillBusy,
+    #[error("cannot do this right now2")]
+    NotRightNow2,
+    #[error("internal server error: {0}")]
+    DBError(#[from] sqlx::Error),
+    #[error("internal server error")]
+    Internal,
+}
+
+impl ServerError {
+    /// The error identifier we send to the client. The official client looks
+    /// these up to show the matching (localized) dialog, so they have to be
+    /// exactly what the official servers send
+    pub fn client_error(&self) -> &'static str {
+        match self {
+            ServerError::InvalidName => "name is not available",
+            ServerError::CharacterExists => "character exists",
+            ServerError::MailTaken => "email is not available",
+            ServerError::InvalidMail => "invalid email",
+            ServerError::PasswordTooShort => "password too short",
+            ServerError::WrongPassword => "wrong pass",
+            ServerError::InvalidAuth
+            | ServerError::SessionExpired
+            | ServerError::LoginCountTooLow => "sessionid invalid",
+            ServerError::UnknownWorld => "server not found",
+            ServerError::PlayerNotFound => "player not found",
+            ServerError::NotEnoughMoney => "need more gold",
+            ServerError::NotEnoughMushrooms => "need more coins",
+            ServerError::NotEnoughThirst => "need more alu",
+            ServerError::TooMuchBeer => "too much beer",
+            ServerError::NotEnoughHourglasses => "need more hourglasses",
+            ServerError::InventoryFull => "inventory full",
+            ServerError::StillBusy => "still busy",
+            ServerError::NotRightNow2 => "cannot do this right now2",
+            ServerError::BadRequest
+            | ServerError::UnknownRequest(_)
+            | ServerError::MissingArgument(_)
+            | ServerError::InvalidArgument(_) => "request not allowed",
+            ServerError::DBError(_) | ServerError::Internal => "server error",
+        }
+    }
+
+    /// Errors, that are our fault, instead of the clients
+    pub fn is_internal(&self) -> bool {
+        matches!(self, ServerError::DBError(_) | ServerError::Internal)
+    }
+
+    /// The body we send to the client
+    pub fn body(&self) -> String {
+        format!("error:{}", self.client_error())
+    }
+}
+
+impl From<ServerError> for Response {
+    fn from(error: ServerError) -> Response {
+        if error.is_internal() {
+            error!("{error}");
+        } else {
+            debug!("Rejected request: {error}");
+        }
+
+        let status = StatusCode::OK;
+        match Response::builder()
+            .status(status)
+            .body(axum::body::Body::new(error.body()))
+        {
+            Ok(resp) => resp,
+            Err(_) => status.into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ResponseBuilder {
+    resp: String,
+    key_start: bool,
+}
+
+impl ResponseBuilder {
+    pub fn add_key(&mut self, key: &str) -> &mut ResponseBuilder {
+        if !self.resp.is_empty() {
+            self.resp.push('&')
+        }
+        self.resp.push_str(key);
+        self.resp.push(':');
+        self.key_start = true;
+        self
+    }
+
+    pub fn add_str(&mut self, val: &str) -> &mut ResponseBuilder {
+        if !self.key_start {
+            self.resp.push('/');
+        } else {
+            self.key_start = false;
+        }
+        self.resp.push_str(val);
+        self
+    }
+
+    pub fn add_val<T: std::fmt::Display>(
+        &mut self,
+        val: T,
+    ) -> &mut ResponseBuilder {
+        if !self.key_start {
+            self.resp.push('/');
+        } else {
+            self.key_start = false;
+        }
+        self.resp.write_fmt(format_args!("{val}")).unwrap();
+        self
+    }
+
+    /// Adds the key of the section, followed by all of its values
+    pub fn add_section<S: ResponseSection>(
+        &mut self,
+        section: &S,
+    ) -> &mut ResponseBuilder {
+        self.add_key(S::KEY);
+        let mut writer = SectionWriter::new(self);
+        section.write_values(&mut writer);
+        debug_assert_eq!(
+            writer.written(),
+            S::LEN,
+            "{} has the wrong length",
+            S::KEY
+        );
+        self
+    }
+
+    pub fn skip_key(&mut self) -> &mut ResponseBuilder {
+        self.key_start = false;
+        self.resp.push('&');
+        self
+    }
+
+    pub fn build<T>(&mut self) -> Result<ServerResponse, T> {
+        let mut a = String::new();
+        std::mem::swap(&mut a, &mut self.resp);
+        Ok(ServerResponse::Data(a))
+    }
+}
+
+impl From<ServerResponse> for Response {
+    fn from(resp: ServerResponse) -> Response {
+        let status = StatusCode::OK;
+
+        let body = match resp {
+            ServerResponse::Success => "Success:".to_string(),
+            ServerResponse::Data(data) => data,
+        };
+        match Response::builder()
+            .status(status)
+            .body(axum::body::Body::new(body))
+        {
+            Ok(resp) => resp,
+            Err(_) => status.into_response(),
+        }
+    }
+}
@@ -0,0 +1,715 @@
+//! Typed versions of the large, positional sections of a response. The
+//! client reads these by index, so every section declares how many values it
+//! has and where its fields are. Writing a section checks both, which means a
+//! field, that is off by one, fails the tests instead of shifting everything
+//! behind it for the client
+
+use std::fmt::Display;
+
+use super::ResponseBuilder;
+
+/// A fixed length list of values, that is sent under a single key
+pub trait ResponseSection {
+    /// The key this section is sent under
+    const KEY: &'static str;
+    /// The amount of values the client expects in this section
+    const LEN: usize;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>);
+}
+
+/// Writes the values of a single section and keeps track of the index the
+/// next value will end up at
+pub struct SectionWriter<'a> {
+    resp: &'a mut ResponseBuilder,
+    written: usize,
+}
+
+impl<'a> SectionWriter<'a> {
+    pub(super) fn new(resp: &'a mut ResponseBuilder) -> Self {
+        SectionWriter { resp, written: 0 }
+    }
+
+    /// The amount of values written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn val(&mut self, val: impl Display) -> &mut Self {
+        self.resp.add_val(val);
+        self.written += 1;
+        self
+    }
+
+    pub fn str(&mut self, val: &str) -> &mut Self {
+        self.resp.add_str(val);
+        self.written += 1;
+        self
+    }
+
+    pub fn vals<T: Display>(
+        &mut self,
+        vals: impl IntoIterator<Item = T>,
+    ) -> &mut Self {
+        for val in vals {
+            self.val(val);
+        }
+        self
+    }
+
+    pub fn item(&mut self, item: &ItemData) -> &mut Self {
+        self.vals(item.0)
+    }
+
+    pub fn items<'i>(
+        &mut self,
+        items: impl IntoIterator<Item = &'i ItemData>,
+    ) -> &mut Self {
+        for item in items {
+            self.item(item);
+        }
+        self
+    }
+
+    pub fn portrait(&mut self, portrait: &PortraitData) -> &mut Self {
+        self.vals([
+            portrait.mouth, portrait.hair, portrait.brows, portrait.eyes,
+            portrait.beards, portrait.nose, portrait.ears, portrait.extra,
+            portrait.horns, portrait.influencer,
+        ])
+    }
+
+    /// Checks, that the next value is written at `idx`
+    pub fn at(&mut self, idx: usize) -> &mut Self {
+        debug_assert_eq!(self.written, idx, "misaligned response section");
+        self
+    }
+
+    /// Fills the section with zeros, until the next value is written at
+    /// `idx`
+    pub fn zeros_until(&mut self, idx: usize) -> &mut Self {
+        debug_assert!(self.written <= idx, "misaligned response section");
+        while self.written < idx {
+            self.val(0);
+        }
+        self
+    }
+}
+
+/// An item, as the client expects it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ItemData(pub [i64; ItemData::LEN]);
+
+impl ItemData {
+    pub const LEN: usize = 12;
+}
+
+/// The portrait of a character, including the influencer portrait
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PortraitData {
+    pub mouth: i64,
+    pub hair: i64,
+    pub brows: i64,
+    pub eyes: i64,
+    pub beards: i64,
+    pub nose: i64,
+    pub ears: i64,
+    pub extra: i64,
+    pub horns: i64,
+    pub influencer: i64,
+}
+
+/// One of the quests offered in the tavern
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QuestOffer {
+    // Flavour 1, Flavour 2 & Monster ID decide =>
+    // - The Line they say
+    // - the quest name
+    // - the quest giver
+    pub flavour1: i64,
+    pub flavour2: i64,
+    /// The monster as stored in the db. The client expects the negated id
+    pub monster: i64,
+    pub location: i64,
+    /// The length in seconds, after the mount has been applied
+    pub length: i64,
+    pub item: ItemData,
+    pub xp: i64,
+    pub silver: i64,
+}
+
+/// Everything in the player save, that belongs to the tavern
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tavern {
+    pub quests: [QuestOffer; 3],
+    /// The thirst for adventure left in seconds
+    pub thirst_for_adventure: i64,
+    pub beer_drunk: i64,
+    pub dice_game_next_free: i64,
+    pub dice_games_remaining: i64,
+}
+
+/// The wares of the weapon, or the magic shop
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Shop {
+    pub items: [ItemData; 6],
+}
+
+/// The state of the own character (`ownplayersave.playerSave`)
+#[derive(Debug, Default, Clone)]
+pub struct PlayerSave {
+    pub player_id: i64,
+    pub level: i64,
+    pub experience: i64,
+    pub next_level_xp: i64,
+    pub honor: i64,
+    pub rank: i64,
+    pub mushrooms_gained: i64,
+    pub portrait: PortraitData,
+    pub race: i64,
+    pub gender: i64,
+    pub class: i64,
+    pub attributes: [i64; 5],
+    /// The attributes gained from equipment
+    pub attribute_bonus: [i64; 5],
+    pub attributes_bought: [i64; 5],
+    pub activity_typ: i64,
+    pub activity_sub_type: i64,
+    pub busy_until: i64,
+    pub equipment: [ItemData; 10],
+    pub inventory: [ItemData; 5],
+    pub tavern: Tavern,
+    pub mount: i64,
+    pub mount_end: i64,
+    pub weapon_shop: Shop,
+    pub magic_shop: Shop,
+    /// Pretty sure this is a bit map of which messages have been seen
+    pub tutorial_status: i64,
+    pub arena_enemies: [i64; 3],
+    /// When the next arena fight is free again
+    pub arena_next_free: i64,
+    /// The current time. Some of the timers are relative to this
+    pub timestamp: i64,
+}
+
+/// Indices of the player save, that other code has to know about
+pub mod save_idx {
+    use super::ItemData;
+
+    pub const LEVEL: usize = 7;
+    pub const PORTRAIT: usize = 17;
+    pub const ATTRIBUTES: usize = 30;
+    pub const ACTIVITY: usize = 45;
+    pub const EQUIPMENT: usize = 48;
+    pub const INVENTORY: usize = EQUIPMENT + 10 * ItemData::LEN;
+    pub const QUESTS: usize = 229;
+    pub const QUEST_ITEMS: usize = 244;
+    pub const MOUNT: usize = 286;
+    pub const WEAPON_SHOP: usize = 287;
+    pub const MAGIC_SHOP: usize = 360;
+    pub const MOUNT_END: usize = 451;
+    pub const THIRST_FOR_ADVENTURE: usize = 456;
+    pub const BEER_DRUNK: usize = 457;
+    pub const ARENA_NEXT_FREE: usize = 460;
+    pub const TUTORIAL_STATUS: usize = 597;
+    pub const ARENA_ENEMIES: usize = 599;
+    pub const DICE_GAMES: usize = 650;
+
+    const _: () = assert!(INVENTORY + 5 * ItemData::LEN == QUESTS - 1);
+    const _: () = assert!(QUEST_ITEMS + 3 * ItemData::LEN + 6 == MOUNT);
+    const _: () = assert!(WEAPON_SHOP + 1 + 6 * ItemData::LEN == MAGIC_SHOP);
+}
+
+impl ResponseSection for PlayerSave {
+    const KEY: &'static str = "ownplayersave.playerSave";
+    const LEN: usize = 759;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>) {
+        use save_idx::*;
+
+        w.val(403127023) // What is this?
+            .val(self.player_id)
+            .val(0)
+            .val(1708336503)
+            .val(1292388336)
+            .zeros_until(LEVEL)
+            .val(self.level) // Level | Arena << 16
+            .val(self.experience)
+            .val(self.next_level_xp)
+            .val(self.honor)
+            .val(self.rank)
+            .val(0) // 12?
+            .val(10) // 13?
+            .val(0) // 14?
+            .val(self.mushrooms_gained)
+            .val(0); // 16?
+
+        w.at(PORTRAIT)
+            .portrait(&self.portrait)
+            .val(self.race)
+            .val(self.gender) // Gender & Mirror
+            .val(self.class);
+
+        w.at(ATTRIBUTES)
+            .vals(self.attributes)
+            .vals(self.attribute_bonus)
+            .vals(self.attributes_bought);
+
+        w.at(ACTIVITY)
+            .val(self.activity_typ) // Current action
+            .val(self.activity_sub_type) // Secondary (time busy)
+            .val(self.busy_until);
+
+        w.at(EQUIPMENT).items(&self.equipment);
+        w.at(INVENTORY).items(&self.inventory);
+
+        w.val(self.timestamp + 60 * 60); // 228
+
+        let quests = &self.tavern.quests;
+        w.at(QUESTS)
+            .vals(quests.iter().map(|q| q.flavour1))
+            .vals(quests.iter().map(|q| q.flavour2))
+            .vals(quests.iter().map(|q| -q.monster))
+            .vals(quests.iter().map(|q| q.location))
+            .vals(quests.iter().map(|q| q.length));
+        w.at(QUEST_ITEMS)
+            .items(quests.iter().map(|q| &q.item))
+            .vals(quests.iter().map(|q| q.xp))
+            .vals(quests.iter().map(|q| q.silver));
+
+        w.at(MOUNT).val(self.mount);
+
+        w.at(WEAPON_SHOP)
+            .val(1708336503)
+            .items(&self.weapon_shop.items);
+        w.at(MAGIC_SHOP)
+            .val(1708336503)
+            .items(&self.magic_shop.items);
+
+        w.zeros_until(434)
+            .val(1) // might be tutorial related?
+            .zeros_until(448)
+            .val(6) // Min damage
+            .val(12) // Max damage
+            .val(112);
+        w.at(MOUNT_END).val(self.mount_end);
+
+        w.zeros_until(455)
+            .val(1708336503)
+            .val(self.tavern.thirst_for_adventure)
+            .val(self.tavern.beer_drunk);
+        w.zeros_until(ARENA_NEXT_FREE)
+            .val(self.arena_next_free)
+            .zeros_until(465)
+            .val(408)
+            .zeros_until(474)
+            .val(-111)
+            .zeros_until(477)
+            .val(4)
+            .val(1708336504)
+            .zeros_until(511)
+            .val(6)
+            .val(2)
+            .zeros_until(515)
+            .val(100) // aura_missing
+            .zeros_until(519)
+            .val(100)
+            .zeros_until(580)
+            .val(self.timestamp + 60 * 10) // wheel_next_free_spin
+            .zeros_until(582)
+            .val(100) // ft honor
+            .zeros_until(584)
+            .val(900)
+            .val(300)
+            .zeros_until(593)
+            .val(3);
+
+        w.zeros_until(TUTORIAL_STATUS).val(self.tutorial_status);
+        w.zeros_until(ARENA_ENEMIES).vals(self.arena_enemies);
+
+        w.zeros_until(610)
+            .val(1708336504)
+            .zeros_until(617)
+            .val(1)
+            .zeros_until(626)
+            .val(30)
+            .zeros_until(649)
+            .val(self.timestamp + 60 * 60); // calendar_next_possible
+
+        w.at(DICE_GAMES)
+            .val(self.tavern.dice_game_next_free)
+            .val(self.tavern.dice_games_remaining);
+
+        w.zeros_until(657)
+            .val(6)
+            .zeros_until(659)
+            .val(2)
+            .zeros_until(671)
+            .val(1950020000000i64)
+            .zeros_until(692)
+            .val(1)
+            .zeros_until(704)
+            .val(1)
+            .zeros_until(758)
+            .str("");
+    }
+}
+
+/// The kind of fight, that is shown to the client
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FightKind {
+    #[default]
+    Quest,
+    Arena,
+}
+
+/// How a fighter looks in the fight screen
+#[derive(Debug, Clone, Copy)]
+pub enum FighterLook {
+    Player {
+        portrait: PortraitData,
+        race: i64,
+        gender: i64,
+    },
+    /// The (negative) id of the monster
+    Monster(i64),
+}
+
+impl Default for FighterLook {
+    fn default() -> Self {
+        FighterLook::Monster(0)
+    }
+}
+
+/// A single participant of a fight
+#[derive(Debug, Default, Clone)]
+pub struct FighterInfo {
+    /// The pid of a player, or the (negative) id of a monster
+    pub id: i64,
+    /// Monsters use their id as their name
+    pub name: String,
+    pub level: i64,
+    pub hp: i64,
+    pub attributes: [i64; 5],
+    pub look: FighterLook,
+    pub class: i64,
+    pub weapon: ItemData,
+    pub offhand: ItemData,
+}
+
+impl FighterInfo {
+    pub const LEN: usize = 47;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>) {
+        let start = w.written();
+        w.val(self.id)
+            .str(&self.name)
+            .val(self.level)
+            .val(self.hp)
+            .val(self.hp)
+            .vals(self.attributes);
+        match &self.look {
+            FighterLook::Player {
+                portrait,
+                race,
+                gender,
+            } => {
+                w.portrait(portrait).val(race).val(gender);
+            }
+            FighterLook::Monster(id) => {
+                w.val(id).vals([0; 11]);
+            }
+        }
+        w.val(self.class).item(&self.weapon).item(&self.offhand);
+        w.at(start + Self::LEN);
+    }
+}
+
+/// The participants of a fight (`fightheader.fighters`)
+#[derive(Debug, Default, Clone)]
+pub struct FightHeader {
+    pub kind: FightKind,
+    pub location: i64,
+    pub fighters: [FighterInfo; 2],
+}
+
+impl ResponseSection for FightHeader {
+    const KEY: &'static str = "fightheader.fighters";
+    const LEN: usize = 5 + 2 * FighterInfo::LEN;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>) {
+        w.val(match self.kind {
+            FightKind::Quest => 1,
+            FightKind::Arena => 0,
+        })
+        .val(0)
+        .val(0)
+        .val(self.location)
+        .val(1);
+        for fighter in &self.fighters {
+            fighter.write_values(w);
+        }
+    }
+}
+
+/// The rewards of a fight (`fightresult.battlereward`)
+#[derive(Debug, Default, Clone)]
+pub struct FightResult {
+    pub won: bool,
+    pub kind: FightKind,
+    pub silver: i64,
+    pub xp: i64,
+    pub mushrooms: i64,
+    pub honor: i64,
+    pub rank_pre: i64,
+    pub rank_post: i64,
+    pub item: ItemData,
+}
+
+impl ResponseSection for FightResult {
+    const KEY: &'static str = "fightresult.battlereward";
+    const LEN: usize = 9 + ItemData::LEN;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>) {
+        w.val(self.won as u8)
+            .val(match self.kind {
+                FightKind::Quest => 0,
+                FightKind::Arena => 1,
+            })
+            .val(self.silver)
+            .val(self.xp)
+            .val(self.mushrooms)
+            .val(self.honor)
+            .val(0)
+            .val(self.rank_pre)
+            .val(self.rank_post)
+            .item(&self.item);
+    }
+}
+
+/// Another character, as seen when looking at them
+/// (`otherplayer.playerlookat`)
+#[derive(Debug, Default, Clone)]
+pub struct LookAt {
+    pub player_id: i64,
+    pub level: i64,
+    pub experience: i64,
+    pub next_level_xp: i64,
+    pub honor: i64,
+    pub rank: i64,
+    pub portrait: PortraitData,
+    pub race: i64,
+    pub gender: i64,
+    pub class: i64,
+    pub attributes: [i64; 5],
+    pub attribute_bonus: [i64; 5],
+    pub equipment: [ItemData; 10],
+    pub mount: i64,
+}
+
+impl ResponseSection for LookAt {
+    const KEY: &'static str = "otherplayer.playerlookat";
+    const LEN: usize = 261;
+
+    fn write_values(&self, w: &mut SectionWriter<'_>) {
+        w.val(self.player_id)
+            .val(0)
+            .val(self.level)
+            .val(self.experience)
+            .val(self.next_level_xp)
+            .val(self.honor)
+            .val(self.rank)
+            .val(0) // ?
+            .portrait(&self.portrait)
+            .val(self.race)
+            .val(self.gender)
+            .val(self.class)
+            .vals(self.attributes)
+            .vals(self.attribute_bonus)
+            .zeros_until(39)
+            .items(&self.equipment);
+
+        w.at(159)
+            .val(self.mount)
+            .vals([
+                58, 37408, 2723, 11901, 0, 0, 1393194397, 1, 4165, 958, 2642,
+                3906638,
+            ])
+            // Mainly fortress stuff
+            .zeros_until(Self::LEN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::ServerResponse;
+
+    fn values<S: ResponseSection>(section: &S) -> Vec<String> {
+        let mut resp = ResponseBuilder::default();
+        resp.add_section(section);
+        let Ok(ServerResponse::Data(data)) = resp.build::<()>() else {
+            panic!("section did not produce any data");
+        };
+        let values = data
+            .strip_prefix(S::KEY)
+            .and_then(|a| a.strip_prefix(':'))
+            .expect("section is missing its key");
+        values.split('/').map(str::to_string).collect()
+    }
+
+    fn item(val: i64) -> ItemData {
+        ItemData([val; ItemData::LEN])
+    }
+
+    #[test]
+    fn player_save_layout() {
+        let quest = |i: i64| QuestOffer {
+            monster: 100 + i,
+            location: 10 + i,
+            item: item(20 + i),
+            silver: 30 + i,
+            ..Default::default()
+        };
+        let save = PlayerSave {
+            player_id: 42,
+            level: 77,
+            portrait: PortraitData {
+                mouth: 5,
+                influencer: 6,
+                ..Default::default()
+            },
+            class: 3,
+            attributes: [11, 12, 13, 14, 15],
+            busy_until: 1234,
+            equipment: [item(1); 10],
+            inventory: [item(2); 5],
+            tavern: Tavern {
+                quests: [quest(1), quest(2), quest(3)],
+                thirst_for_adventure: 6000,
+                beer_drunk: 4,
+                dice_game_next_free: 999,
+                dice_games_remaining: 10,
+            },
+            mount: 4,
+            mount_end: 4567,
+            weapon_shop: Shop {
+                items: [item(7); 6],
+            },
+            magic_shop: Shop {
+                items: [item(8); 6],
+            },
+            tutorial_status: 0xFF,
+            arena_enemies: [31, 32, 33],
+            arena_next_free: 5678,
+            ..Default::default()
+        };
+        let vals = values(&save);
+        assert_eq!(vals.len(), PlayerSave::LEN);
+
+        use save_idx::*;
+        assert_eq!(vals[1], "42");
+        assert_eq!(vals[LEVEL], "77");
+        assert_eq!(vals[PORTRAIT], "5");
+        assert_eq!(vals[PORTRAIT + 9], "6");
+        assert_eq!(vals[29], "3");
+        assert_eq!(
+            vals[ATTRIBUTES..ATTRIBUTES + 5],
+            ["11", "12", "13", "14", "15"]
+        );
+        assert_eq!(vals[ACTIVITY + 2], "1234");
+        assert_eq!(vals[EQUIPMENT], "1");
+        assert_eq!(vals[INVENTORY - 1], "1");
+        assert_eq!(vals[INVENTORY], "2");
+        assert_eq!(
+            vals[QUESTS + 6..QUESTS + 12],
+            ["-101", "-102", "-103", "11", "12", "13"]
+        );
+        assert_eq!(vals[QUEST_ITEMS], "21");
+        assert_eq!(vals[QUEST_ITEMS + 2 * ItemData::LEN], "23");
+        assert_eq!(vals[MOUNT - 3..MOUNT], ["31", "32", "33"]);
+        assert_eq!(vals[MOUNT], "4");
+        assert_eq!(vals[WEAPON_SHOP + 1], "7");
+        assert_eq!(vals[MAGIC_SHOP - 1], "7");
+        assert_eq!(vals[MAGIC_SHOP + 1], "8");
+        assert_eq!(vals[MAGIC_SHOP + 6 * ItemData::LEN], "8");
+        assert_eq!(vals[MOUNT_END], "4567");
+        assert_eq!(vals[THIRST_FOR_ADVENTURE], "6000");
+        assert_eq!(vals[BEER_DRUNK], "4");
+        assert_eq!(vals[ARENA_NEXT_FREE], "5678");
+        assert_eq!(vals[TUTORIAL_STATUS], "255");
+        assert_eq!(vals[ARENA_ENEMIES..ARENA_ENEMIES + 3], ["31", "32", "33"]);
+        assert_eq!(vals[DICE_GAMES..DICE_GAMES + 2], ["999", "10"]);
+        assert_eq!(vals[PlayerSave::LEN - 1], "");
+    }
+
+    #[test]
+    fn fight_layout() {
+        let player = FighterInfo {
+            id: 1,
+            name: "Player".to_string(),
+            look: FighterLook::Player {
+                portrait: PortraitData::default(),
+                race: 1,
+                gender: 2,
+            },
+            class: 4,
+            weapon: item(5),
+            ..Default::default()
+        };
+        let monster = FighterInfo {
+            id: -139,
+            name: "-139".to_string(),
+            look: FighterLook::Monster(-139),
+            class: 3,
+            ..Default::default()
+        };
+        let header = FightHeader {
+            kind: FightKind::Quest,
+            location: 9,
+            fighters: [player, monster],
+        };
+        let vals = values(&header);
+        assert_eq!(vals.len(), FightHeader::LEN);
+        assert_eq!(vals[..5], ["1", "0", "0", "9", "1"]);
+        assert_eq!(vals[6], "Player");
+        assert_eq!(vals[5 + 22], "4");
+        assert_eq!(vals[5 + 23], "5");
+        assert_eq!(vals[5 + FighterInfo::LEN], "-139");
+        assert_eq!(vals[5 + FighterInfo::LEN + 10], "-139");
+        assert_eq!(vals[5 + FighterInfo::LEN + 22], "3");
+
+        let result = FightResult {
+            won: true,
+            kind: FightKind::Arena,
+            rank_post: 2,
+            item: item(1),
+            ..Default::default()
+        };
+        let vals = values(&result);
+        assert_eq!(vals.len(), FightResult::LEN);
+        assert_eq!(vals[..2], ["1", "1"]);
+        assert_eq!(vals[8], "2");
+        assert_eq!(vals[9], "1");
+    }
+
+    #[test]
+    fn look_at_layout() {
+        let look_at = LookAt {
+            player_id: 3,
+            class: 2,
+            attributes: [1, 2, 3, 4, 5],
+            equipment: [item(9); 10],
+            mount: 4,
+            ..Default::default()
+        };
+        let vals = values(&look_at);
+        assert_eq!(vals.len(), LookAt::LEN);
+        assert_eq!(vals[0], "3");
+        assert_eq!(vals[20], "2");
+        assert_eq!(vals[21..26], ["1", "2", "3", "4", "5"]);
+        assert_eq!(vals[39], "9");
+        assert_eq!(vals[158], "9");
+        assert_eq!(vals[159], "4");
+    }
+}
@@ -0,0 +1,208 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+use fastrand::Rng;
+use log::{error, info};
+
+use crate::{
+    command::{
+        now, reroll_quests, reroll_shop, resolve_guild_fights,
+        roll_daily_tasks, MAGIC_SHOP, WEAPON_SHOP,
+    },
+    config::get_config,
+    db::DbPool,
+    ServerError,
+};
+
+type JobFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<(), ServerError>> + Send + 'a>>;
+
+/// Something, that has to happen regularly, independent of any request
+struct Job {
+    name: &'static str,
+    /// How often (in seconds) the job runs. The job runs, whenever a new
+    /// interval starts, so daily jobs run at midnight (UTC)
+    interval: i64,
+    /// Runs the job for the interval, that started at the given time
+    run: for<'a> fn(&'a DbPool, i64) -> JobFuture<'a>,
+}
+
+static JOBS: &[Job] = &[
+    Job {
+        name: "daily_reset",
+        interval: 24 * 60 * 60,
+        run: |db, day| Box::pin(daily_reset(db, day)),
+    },
+    Job {
+        name: "guild_fights",
+        interval: 60 * 60,
+        run: |db, _| Box::pin(resolve_guild_fights(db)),
+    },
+    Job {
+        name: "expire_mounts",
+        interval: 60 * 60,
+        run: |db, _| Box::pin(expire_mounts(db)),
+    },
+    Job {
+        name: "expire_sessions",
+        interval: 60 * 60,
+        run: |db, _| Box::pin(expire_sessions(db)),
+    },
+];
+
+/// How often we check, if a job is due
+const TICK: Duration = Duration::from_secs(60);
+
+/// Runs all jobs in the background, whenever they are due
+pub fn spawn_scheduler(db: DbPool) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = run_due_jobs(&db, now()).await {
+                error!("Could not run scheduled jobs: {e}");
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
+
+/// Runs every job, that has not yet run in its current interval, and returns
+/// the names of the jobs, that have been run. Jobs, that we have never seen
+/// before, first run in the interval after this one
+pub async fn run_due_jobs(
+    db: &DbPool,
+    now: i64,
+) -> Result<Vec<&'static str>, ServerError> {
+    let mut ran = vec![];
+    for job in JOBS {
+        sqlx::query!(
+            "INSERT INTO scheduled_job (name, last_run) VALUES ($1, $2)
+             ON CONFLICT (name) DO NOTHING",
+            job.name,
+            now
+        )
+        .execute(db)
+        .await?;
+
+        let previous_run = sqlx::query_scalar!(
+            "SELECT last_run FROM scheduled_job WHERE name = $1", job.name
+        )
+        .fetch_one(db)
+        .await?;
+        let interval_start = now - now.rem_euclid(job.interval);
+        if previous_run >= interval_start {
+            continue;
+        }
+
+        // Claiming the job before running it makes sure, that only one of
+        // multiple servers on the same db runs it
+        let claimed = sqlx::query!(
+            "UPDATE scheduled_job SET last_run = $2
+             WHERE name = $1 AND last_run = $3",
+            job.name,
+            now,
+            previous_run
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            continue;
+        }
+
+        info!("Running scheduled job {}", job.name);
+        if let Err(e) = (job.run)(db, interval_start).await {
+            error!("Scheduled job {} failed: {e}", job.name);
+            // Try again on the next tick
+            sqlx::query!(
+                "UPDATE scheduled_job SET last_run = $2 WHERE name = $1",
+                job.name, previous_run
+            )
+            .execute(db)
+            .await?;
+            continue;
+        }
+        ran.push(job.name);
+    }
+    Ok(ran)
+}
+
+/// Starts the day, that began at `day`, for every character, that has not
+/// had its reset for it yet. That refills the tavern, resets the arena
+/// cooldown and rolls new quests, wares and daily tasks. Every character is
+/// reset in its own transaction together with its `last_daily_reset`, so
+/// retrying a failed reset skips everyone, that is already done
+pub async fn daily_reset(db: &DbPool, day: i64) -> Result<(), ServerError> {
+    let mut rng = Rng::new();
+    let characters = sqlx::query!(
+        "SELECT pid, level, class, typ as activity
+         FROM character NATURAL JOIN activity
+         WHERE last_daily_reset < $1",
+        day
+    )
+    .fetch_all(db)
+    .await?;
+
+    for character in &characters {
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            "UPDATE tavern
+             SET tfa = 6000, beer_drunk = 0, dice_games_remaining = 10
+             WHERE pid = $1",
+            character.pid
+        )
+        .execute(&mut *tx)
+        .await?;
+        // The quests of a character, that is on one, are still needed to
+        // finish it
+        if character.activity != 2 {
+            reroll_quests(
+                &mut tx, &mut rng, character.pid, character.level,
+                character.class,
+            )
+            .await?;
+        }
+        for shop in [WEAPON_SHOP, MAGIC_SHOP] {
+            reroll_shop(
+                &mut tx, &mut rng, character.pid, character.level,
+                character.class, shop,
+            )
+            .await?;
+        }
+        roll_daily_tasks(&mut tx, &mut rng, character.pid).await?;
+        sqlx::query!(
+            "UPDATE character SET arena_next_free = 0, last_daily_reset = $2
+             WHERE pid = $1",
+            character.pid,
+            day
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+    info!("Did the daily reset of {} characters", characters.len());
+    Ok(())
+}
+
+/// Takes away mounts, that have run out. Requests check this on their own,
+/// this just keeps the db in line with what they see
+async fn expire_mounts(db: &DbPool) -> Result<(), ServerError> {
+    sqlx::query!(
+        "UPDATE character SET mount = 0, mount_end = 0
+         WHERE mount_end > 0 AND mount_end < $1",
+        now()
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Deletes sessions, that can no longer be used
+async fn expire_sessions(db: &DbPool) -> Result<(), ServerError> {
+    let res = sqlx::query!(
+        "DELETE FROM session WHERE last_active < $1",
+        now() - get_config().server.session_timeout
+    )
+    .execute(db)
+    .await?;
+    info!("Deleted {} expired sessions", res.rows_affected());
+    Ok(())
+}
@@ -0,0 +1,1197 @@
+//! Drives the router in-process, the same way the game client would
+
+use std::collections::HashMap;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use base64::Engine;
+use sf_api::{
+    gamestate::items::Enchantment,
+    misc::{encrypt_server_request, sha1_hash, HASH_CONST},
+};
+use sf_server::{
+    build_router,
+    command::repair_quests,
+    db::{test_db, DbPool},
+    scheduler::{daily_reset, run_due_jobs},
+    DEFAULT_CRYPTO_ID, DEFAULT_CRYPTO_KEY, DEFAULT_SESSION_ID,
+};
+use tower::ServiceExt;
+
+const PASSWORD: &str = "secret123";
+
+struct TestServer {
+    app: Router,
+    db: DbPool,
+}
+
+impl TestServer {
+    async fn new() -> TestServer {
+        let db = test_db().await;
+        TestServer {
+            app: build_router(db.clone()),
+            db,
+        }
+    }
+
+    async fn get(&self, uri: &str) -> GameResponse {
+        let req = Request::builder()
+            .uri(uri)
+            .header("host", "localhost")
+            .body(Body::empty())
+            .unwrap();
+        let resp = self.app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        GameResponse::parse(&String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Sends an unencrypted command, like the client does before login
+    async fn cmd(&self, command: &str, args: &str) -> GameResponse {
+        let params = base64::engine::general_purpose::URL_SAFE.encode(args);
+        self.get(&format!(
+            "/cmd.php?req={command}&sid={DEFAULT_CRYPTO_ID}&params={params}"
+        ))
+        .await
+    }
+
+    async fn create_character(&self, name: &str) {
+        let resp = self
+            .cmd(
+                "AccountCreate",
+                &format!(
+                    "{name}/{PASSWORD}/{}@example.com/1/1/1/1,1,1,1,1,1,1,1,1",
+                    name.to_lowercase()
+                ),
+            )
+            .await;
+        assert_eq!(resp.get("tracking.s"), Some("signup"), "{resp:?}");
+
+        // Finished daily tasks pay out mushrooms, which would make the
+        // resources the tests check random. Tests, that need tasks, add them
+        sqlx::query(
+            "DELETE FROM daily_task
+             WHERE pid = (SELECT pid FROM character WHERE name = $1)",
+        )
+        .bind(name)
+        .execute(&self.db)
+        .await
+        .unwrap();
+    }
+
+    /// The ids of the quests offered in the tavern
+    async fn quests(&self, pid: i64) -> [i64; 3] {
+        let (q1, q2, q3) = sqlx::query_as(
+            "SELECT quest1, quest2, quest3 FROM tavern WHERE pid = $1",
+        )
+        .bind(pid)
+        .fetch_one(&self.db)
+        .await
+        .unwrap();
+        [q1, q2, q3]
+    }
+
+    /// Puts a new item of the given type and class into the bag
+    async fn give_item(&self, pid: i64, pos: i64, typ: i64, class: i64) {
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO item (item_type, model_id, class, silver, mushrooms)
+             VALUES ($1, 1, $2, 100, 0) RETURNING id",
+        )
+        .bind(typ)
+        .bind(class)
+        .fetch_one(&self.db)
+        .await
+        .unwrap();
+        sqlx::query(&format!("UPDATE bag SET pos{pos} = $1 WHERE pid = $2"))
+            .bind(id)
+            .bind(pid)
+            .execute(&self.db)
+            .await
+            .unwrap();
+    }
+
+    async fn thirst(&self, pid: i64) -> i64 {
+        sqlx::query_scalar("SELECT tfa FROM tavern WHERE pid = $1")
+            .bind(pid)
+            .fetch_one(&self.db)
+            .await
+            .unwrap()
+    }
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// The start of the next day, which is when the next daily reset happens
+fn tomorrow() -> i64 {
+    let now = now();
+    now - now.rem_euclid(24 * 60 * 60) + 24 * 60 * 60
+}
+
+/// The credentials of a (possibly logged in) client
+struct TestClient {
+    session_id: String,
+    crypto_id: String,
+    crypto_key: String,
+    /// Increased with every request, like the real client does
+    login_count: i64,
+}
+
+impl TestClient {
+    fn new() -> TestClient {
+        TestClient {
+            session_id: DEFAULT_SESSION_ID.to_string(),
+            crypto_id: DEFAULT_CRYPTO_ID.to_string(),
+            crypto_key: DEFAULT_CRYPTO_KEY.to_string(),
+            login_count: 1,
+        }
+    }
+
+    /// Sends an encrypted command via `req.php`
+    async fn req(
+        &mut self,
+        server: &TestServer,
+        command: &str,
+        args: &str,
+    ) -> GameResponse {
+        let encrypted = encrypt_server_request(
+            &format!("{}|{command}:{args}|", self.session_id),
+            &self.crypto_key,
+        );
+        let resp = server
+            .get(&format!(
+                "/req.php?req={}{encrypted}&c={}",
+                self.crypto_id, self.login_count
+            ))
+            .await;
+        self.login_count += 1;
+
+        // Logging in hands out new credentials
+        if let Some(session_id) = resp.get("sessionid") {
+            self.session_id = session_id.to_string();
+        }
+        if let Some(crypto_id) = resp.get("cryptoid") {
+            self.crypto_id = crypto_id.to_string();
+        }
+        if let Some(crypto_key) = resp.get("cryptokey") {
+            self.crypto_key = crypto_key.to_string();
+        }
+        resp
+    }
+
+    async fn login(&mut self, server: &TestServer, name: &str) -> GameResponse {
+        let resp = self.try_login(server, name).await;
+        assert_eq!(resp.get("ownplayername.r"), Some(name), "{resp:?}");
+        resp
+    }
+
+    /// Sends `AccountLogin` with the current login count
+    async fn try_login(
+        &mut self,
+        server: &TestServer,
+        name: &str,
+    ) -> GameResponse {
+        let login_count = self.login_count;
+        let pw_hash = sha1_hash(&format!("{PASSWORD}{HASH_CONST}"));
+        let full_hash = sha1_hash(&format!("{pw_hash}{login_count}"));
+        self.req(
+            server,
+            "AccountLogin",
+            &format!("{name}/{full_hash}/{login_count}"),
+        )
+        .await
+    }
+}
+
+/// The `key:val/val&key:val` response of the server
+#[derive(Debug)]
+struct GameResponse(HashMap<String, String>);
+
+impl GameResponse {
+    fn parse(body: &str) -> GameResponse {
+        GameResponse(
+            body.split('&')
+                .filter_map(|a| a.split_once(':'))
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn values(&self, key: &str) -> Vec<&str> {
+        let val = self.get(key).unwrap_or_else(|| panic!("{key} missing"));
+        val.split('/').collect()
+    }
+
+    fn int(&self, key: &str, idx: usize) -> i64 {
+        self.values(key)[idx].parse().unwrap()
+    }
+
+    fn error(&self) -> Option<&str> {
+        self.get("error")
+    }
+}
+
+const SAVE: &str = "ownplayersave.playerSave";
+/// The index of the time the next arena fight is free again in the save
+const ARENA_NEXT_FREE: usize = 460;
+/// The index of the (mounted) length of the first quest in the save
+const QUEST_LENGTHS: usize = 229 + 12;
+
+#[tokio::test]
+async fn login_and_finish_quest() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    assert_eq!(resp.values(SAVE).len(), 759);
+    let pid = resp.int(SAVE, 1);
+    assert!(pid > 0);
+    let silver = resp.int("resources", 2);
+
+    let resp = client.req(&server, "PlayerAdventureStart", "1/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 2, "should be questing");
+    assert_eq!(resp.int(SAVE, 46), 1, "should be on the first quest");
+
+    let resp = client.req(&server, "PlayerAdventureFinished", "").await;
+    assert_eq!(resp.error(), Some("still busy"));
+
+    sqlx::query("UPDATE activity SET busy_until = 0 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let resp = client.req(&server, "PlayerAdventureFinished", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.values("fightheader.fighters").len(), 99);
+    assert_eq!(resp.int("fightheader.fighters", 5), pid);
+    let reward = "fightresult.battlereward";
+    assert_eq!(resp.values(reward).len(), 21);
+    let won = resp.int(reward, 0) == 1;
+    let winner = resp.int("winnerid", 0);
+    assert_eq!(won, winner == pid, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be questing");
+    assert_eq!(resp.int("resources", 2), silver + resp.int(reward, 2));
+}
+
+#[tokio::test]
+async fn lose_quest() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+
+    // A high level character without any attributes has no chance against
+    // the monsters of its level
+    sqlx::query(
+        "UPDATE attributes SET strength = 1, dexterity = 1, intelligence = 1,
+                               stamina = 1, luck = 1
+         WHERE id = (SELECT attributes FROM character WHERE pid = $1)",
+    )
+    .bind(pid)
+    .execute(&server.db)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE character SET level = 300 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let before = server.quests(pid).await;
+
+    let resp = client.req(&server, "PlayerAdventureStart", "1/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let silver = resp.int("resources", 2);
+    let resp = client.req(&server, "PlayerAdventureFinished", "2").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+
+    let reward = "fightresult.battlereward";
+    assert_eq!(resp.int(reward, 0), 0, "should have lost");
+    assert_ne!(resp.int("winnerid", 0), pid);
+    assert_eq!(resp.int(reward, 2), 0, "no silver");
+    assert_eq!(resp.int(reward, 3), 0, "no experience");
+    assert_eq!(resp.int("resources", 2), silver);
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be questing");
+
+    let after = server.quests(pid).await;
+    assert!(
+        after.iter().all(|a| !before.contains(a)),
+        "should be rerolled"
+    );
+}
+
+#[tokio::test]
+async fn skip_quests() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let glasses = resp.int("resources", 4);
+    assert!(glasses > 0);
+
+    client.req(&server, "PlayerAdventureStart", "1/0").await;
+    let resp = client.req(&server, "PlayerAdventureFinished", "0").await;
+    assert_eq!(resp.error(), Some("still busy"));
+
+    // Every started minute, that is left, costs an hourglass
+    sqlx::query("UPDATE activity SET busy_until = $1")
+        .bind(now() + 4 * 60 + 30)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerAdventureFinished", "2").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be questing");
+    assert_eq!(resp.int("resources", 4), glasses - 5);
+    let quicksand: i64 = sqlx::query_scalar("SELECT quicksand FROM tavern")
+        .fetch_one(&server.db)
+        .await
+        .unwrap();
+    assert_eq!(quicksand, glasses - 5);
+    let mushrooms = resp.int("resources", 1);
+
+    client.req(&server, "PlayerAdventureStart", "1/0").await;
+    let resp = client.req(&server, "PlayerAdventureFinished", "1").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let reward = resp.int("fightresult.battlereward", 4);
+    assert_eq!(resp.int("resources", 1), mushrooms - 1 + reward);
+    assert_eq!(resp.int("resources", 4), glasses - 5);
+
+    sqlx::query("UPDATE tavern SET quicksand = 1")
+        .execute(&server.db)
+        .await
+        .unwrap();
+    client.req(&server, "PlayerAdventureStart", "1/0").await;
+    sqlx::query("UPDATE activity SET busy_until = $1")
+        .bind(now() + 3 * 60)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerAdventureFinished", "2").await;
+    assert_eq!(resp.error(), Some("need more hourglasses"));
+}
+
+#[tokio::test]
+async fn cancel_quest_refunds_thirst() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let before = server.thirst(pid).await;
+
+    let resp = client.req(&server, "PlayerAdventureStop", "").await;
+    assert_eq!(resp.error(), Some("request not allowed"));
+
+    let resp = client.req(&server, "PlayerAdventureStart", "2/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert!(server.thirst(pid).await < before);
+
+    let resp = client.req(&server, "PlayerAdventureStop", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be questing");
+    assert_eq!(server.thirst(pid).await, before);
+
+    // With a griffin, quests take half as long and cost half the thirst
+    sqlx::query(
+        "UPDATE character SET mount = 4, mount_end = 4102444800 WHERE pid = $1",
+    )
+    .bind(pid)
+    .execute(&server.db)
+    .await
+    .unwrap();
+    let resp = client.req(&server, "Poll", "").await;
+    let length = resp.int(SAVE, QUEST_LENGTHS + 1);
+    let resp = client.req(&server, "PlayerAdventureStart", "2/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(server.thirst(pid).await, before - length);
+    let resp = client.req(&server, "PlayerAdventureStop", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(server.thirst(pid).await, before);
+}
+
+#[tokio::test]
+async fn city_guard() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let silver = resp.int("resources", 2);
+
+    let resp = client.req(&server, "PlayerWorkStart", "3").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 1, "should be working");
+    assert_eq!(resp.int(SAVE, 46), 3, "should be working for 3 hours");
+
+    let resp = client.req(&server, "PlayerAdventureStart", "1/0").await;
+    assert_eq!(resp.error(), Some("still busy"));
+
+    let resp = client.req(&server, "PlayerWorkCancel", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be working");
+    assert_eq!(resp.int("resources", 2), silver, "cancelling pays nothing");
+
+    client.req(&server, "PlayerWorkStart", "2").await;
+    let resp = client.req(&server, "PlayerWorkFinished", "").await;
+    assert_eq!(resp.error(), Some("still busy"));
+
+    sqlx::query("UPDATE activity SET busy_until = 0 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    // Finished work can not be cancelled, that would throw away the wage
+    let resp = client.req(&server, "PlayerWorkCancel", "").await;
+    assert_eq!(resp.error(), Some("request not allowed"));
+
+    let resp = client.req(&server, "PlayerWorkFinished", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be working");
+    // Level 1 characters earn 350 silver per hour
+    assert_eq!(resp.int("resources", 2), silver + 2 * 350);
+}
+
+#[tokio::test]
+async fn dice_game() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let mushrooms = resp.int("resources", 1);
+
+    let resp = client.req(&server, "RollDice", "0/0/0/0/0/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let first: Vec<i64> = resp
+        .values("dicestatus")
+        .iter()
+        .map(|a| a.parse().unwrap())
+        .collect();
+    assert_eq!(first.len(), 5);
+    assert!(first.iter().all(|a| (1..=6).contains(a)), "{first:?}");
+    assert_eq!(resp.get("dicereward"), None);
+
+    // Keep the first two dice and reroll the rest
+    let args = format!("0/{}/{}/0/0/0", first[0], first[1]);
+    let resp = client.req(&server, "RollDice", &args).await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let second = resp.values("dicestatus");
+    assert_eq!(second[..2], [first[0], first[1]].map(|a| a.to_string()));
+    let reward = resp.values("dicereward");
+    assert_eq!(reward.len(), 2);
+
+    let resp = client.req(&server, "RollDice", "0/0/0/0/0/0").await;
+    assert_eq!(resp.error(), Some("still busy"), "free game is on cooldown");
+
+    let resp = client.req(&server, "RollDice", "1/0/0/0/0/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let won_mushrooms = match reward[0] {
+        "4" => reward[1].parse::<i64>().unwrap(),
+        _ => 0,
+    };
+    assert_eq!(resp.int("resources", 1), mushrooms + won_mushrooms - 1);
+
+    // The second roll is already paid for, whatever price the client sends
+    let mushrooms = resp.int("resources", 1);
+    let resp = client.req(&server, "RollDice", "1/0/0/0/0/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert!(resp.get("dicereward").is_some(), "{resp:?}");
+    let reward = resp.values("dicereward");
+    let won_mushrooms = match reward[0] {
+        "4" => reward[1].parse::<i64>().unwrap(),
+        _ => 0,
+    };
+    assert_eq!(resp.int("resources", 1), mushrooms + won_mushrooms);
+
+    sqlx::query("UPDATE tavern SET quicksand = 0")
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "RollDice", "2/0/0/0/0/0").await;
+    assert_eq!(resp.error(), Some("need more hourglasses"));
+}
+
+#[tokio::test]
+async fn beer_and_daily_reset() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let mushrooms = resp.int("resources", 1);
+    let thirst = server.thirst(pid).await;
+
+    for beers in 1..=10 {
+        let resp = client.req(&server, "PlayerBeerBuy", "").await;
+        assert_eq!(resp.error(), None, "{resp:?}");
+        assert_eq!(resp.int("resources", 1), mushrooms - beers);
+    }
+    assert_eq!(server.thirst(pid).await, thirst + 10 * 20 * 60);
+
+    let resp = client.req(&server, "PlayerBeerBuy", "").await;
+    assert_eq!(resp.error(), Some("too much beer"));
+
+    daily_reset(&server.db, tomorrow()).await.unwrap();
+    assert_eq!(server.thirst(pid).await, 6000);
+    let resp = client.req(&server, "PlayerBeerBuy", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+
+    // A Thirsty Wanderer belt allows an extra beer
+    let belt: i64 = sqlx::query_scalar(
+        "INSERT INTO item (item_type, enchantment, model_id, silver, mushrooms)
+         VALUES (7, $1, 1, 0, 0) RETURNING id",
+    )
+    .bind(Enchantment::ThirstyWanderer as i64)
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE equipment SET belt = $1 WHERE pid = $2")
+        .bind(belt)
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    for _ in 2..=11 {
+        let resp = client.req(&server, "PlayerBeerBuy", "").await;
+        assert_eq!(resp.error(), None, "{resp:?}");
+    }
+    let resp = client.req(&server, "PlayerBeerBuy", "").await;
+    assert_eq!(resp.error(), Some("too much beer"));
+}
+
+#[tokio::test]
+async fn scheduled_daily_reset() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+    server.create_character("Bob").await;
+    let now = now();
+    let tomorrow = now + 24 * 60 * 60;
+    let pids: Vec<i64> =
+        sqlx::query_scalar("SELECT pid FROM character ORDER BY name")
+            .fetch_all(&server.db)
+            .await
+            .unwrap();
+    let (alice, bob) = (pids[0], pids[1]);
+
+    assert!(run_due_jobs(&server.db, now).await.unwrap().is_empty());
+    sqlx::query("UPDATE tavern SET tfa = 0")
+        .execute(&server.db)
+        .await
+        .unwrap();
+    // Bob has already been reset, before the last attempt failed
+    sqlx::query("UPDATE character SET last_daily_reset = $1 WHERE pid = $2")
+        .bind(tomorrow - tomorrow.rem_euclid(24 * 60 * 60))
+        .bind(bob)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let alice_quests = server.quests(alice).await;
+    let bob_quests = server.quests(bob).await;
+
+    let ran = run_due_jobs(&server.db, tomorrow).await.unwrap();
+    assert!(ran.contains(&"daily_reset"), "{ran:?}");
+    assert_eq!(server.thirst(alice).await, 6000);
+    assert_ne!(server.quests(alice).await, alice_quests);
+    assert_eq!(server.thirst(bob).await, 0);
+    assert_eq!(server.quests(bob).await, bob_quests);
+
+    assert!(run_due_jobs(&server.db, tomorrow).await.unwrap().is_empty());
+
+    // Running the reset for the same day again does nothing
+    let alice_quests = server.quests(alice).await;
+    daily_reset(&server.db, tomorrow - tomorrow.rem_euclid(24 * 60 * 60))
+        .await
+        .unwrap();
+    assert_eq!(server.quests(alice).await, alice_quests);
+}
+
+#[tokio::test]
+async fn daily_tasks() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let mushrooms = resp.int("resources", 1);
+    assert_eq!(resp.values("dailytasklist"), ["98"]);
+
+    // Drinking beer twice only counts up to the target and pays out once
+    sqlx::query(
+        "INSERT INTO daily_task (pid, pos, typ, target) VALUES ($1, 0, 2, 1)",
+    )
+    .bind(pid)
+    .execute(&server.db)
+    .await
+    .unwrap();
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.values("dailytasklist"), ["98", "2", "0", "1", "1"]);
+    for _ in 0..2 {
+        let resp = client.req(&server, "PlayerBeerBuy", "").await;
+        assert_eq!(resp.error(), None, "{resp:?}");
+    }
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.values("dailytasklist"), ["98", "2", "1", "1", "1"]);
+    assert_eq!(resp.int("resources", 1), mushrooms - 2 + 1);
+
+    daily_reset(&server.db, tomorrow()).await.unwrap();
+    let resp = client.req(&server, "Poll", "").await;
+    let tasks = resp.values("dailytasklist");
+    assert_eq!(tasks.len(), 1 + 3 * 4, "{tasks:?}");
+    for task in tasks[1..].chunks(4) {
+        assert_eq!(task[1], "0", "{tasks:?}");
+    }
+}
+
+#[tokio::test]
+async fn guild_fights() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+    server.create_character("Bob").await;
+    let pids: Vec<i64> =
+        sqlx::query_scalar("SELECT pid FROM character ORDER BY name")
+            .fetch_all(&server.db)
+            .await
+            .unwrap();
+    sqlx::query("UPDATE character SET level = 10 WHERE pid = $1")
+        .bind(pids[0])
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let mut guilds = vec![];
+    for (name, pid) in ["Attackers", "Defenders"].into_iter().zip(&pids) {
+        let guild: i64 = sqlx::query_scalar(
+            "INSERT INTO guild (name, emblem, created, hydra_current_life)
+             VALUES ($1, '', 0, 0) RETURNING id",
+        )
+        .bind(name)
+        .fetch_one(&server.db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO guild_member (pid, guild_id, rank, joined, \
+             last_active, is_attacking, is_defending)
+             VALUES ($1, $2, 3, 0, 0, TRUE, TRUE)",
+        )
+        .bind(pid)
+        .bind(guild)
+        .execute(&server.db)
+        .await
+        .unwrap();
+        guilds.push(guild);
+    }
+    sqlx::query("UPDATE guild SET attacking = $1 WHERE id = $2")
+        .bind(guilds[1])
+        .bind(guilds[0])
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let now = now();
+    run_due_jobs(&server.db, now).await.unwrap();
+    let ran = run_due_jobs(&server.db, now + 60 * 60).await.unwrap();
+    assert!(ran.contains(&"guild_fights"), "{ran:?}");
+
+    let honor: Vec<(i64, Option<i64>)> =
+        sqlx::query_as("SELECT honor, attacking FROM guild ORDER BY name")
+            .fetch_all(&server.db)
+            .await
+            .unwrap();
+    // The attackers have the higher level and win
+    assert_eq!(honor, [(220, None), (180, None)]);
+    let signed_up: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM guild_member WHERE is_attacking OR is_defending",
+    )
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    assert_eq!(signed_up, 2, "only the fought sides are cleared");
+}
+
+#[tokio::test]
+async fn repairs_broken_quests() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+    server.create_character("Bob").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    assert_eq!(repair_quests(&server.db).await.unwrap(), 0);
+
+    client.req(&server, "PlayerAdventureStart", "1/0").await;
+    let thirst = server.thirst(pid).await;
+    sqlx::query(
+        "UPDATE activity SET sub_type = 7, busy_until = 0 WHERE pid = $1",
+    )
+    .bind(pid)
+    .execute(&server.db)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE tavern SET quest3 = quest1 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let resp = client.req(&server, "PlayerAdventureFinished", "").await;
+    assert_eq!(resp.error(), Some("server error"));
+
+    assert_eq!(repair_quests(&server.db).await.unwrap(), 2);
+    assert_eq!(repair_quests(&server.db).await.unwrap(), 0);
+    assert!(
+        server.thirst(pid).await > thirst,
+        "should have been refunded"
+    );
+
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, 45), 0, "should no longer be questing");
+}
+
+#[tokio::test]
+async fn items_from_database() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+
+    let weapon: i64 = sqlx::query_scalar(
+        "INSERT INTO item (item_type, model_id, class, atr_typ1, atr_val1, \
+         silver, mushrooms)
+         VALUES (1, 3, 1, 3, 7, 250, 2) RETURNING id",
+    )
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE equipment SET weapon = $1 WHERE pid = $2")
+        .bind(weapon)
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let potion: i64 = sqlx::query_scalar(
+        "INSERT INTO item (item_type, model_id, expires, silver, mushrooms)
+         VALUES (12, 5, 1234, 10, 0) RETURNING id",
+    )
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE bag SET pos2 = $1 WHERE pid = $2")
+        .bind(potion)
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let save = resp.values(SAVE);
+    let item = |idx: usize| &save[idx..idx + 12];
+    // The weapon is the 9th equipment slot
+    assert_eq!(
+        item(48 + 8 * 12),
+        ["1", "1003", "0", "0", "3", "0", "0", "7", "0", "0", "250", "2"]
+    );
+    assert!(item(48).iter().all(|a| *a == "0"), "hat should be empty");
+    // Only the equipped weapon adds to the attributes
+    assert_eq!(save[30 + 5..30 + 10], ["0", "0", "7", "0", "0"]);
+    assert_eq!(
+        item(168 + 12),
+        ["12", "5", "0", "0", "1234", "0", "0", "0", "0", "0", "10", "0"]
+    );
+
+    // The items offered by the quests are shown as well
+    let (q1, q2, q3): (Option<i64>, Option<i64>, Option<i64>) = sqlx::query_as(
+        "SELECT q1.item, q2.item, q3.item FROM tavern
+             JOIN quest as q1 ON q1.id = quest1
+             JOIN quest as q2 ON q2.id = quest2
+             JOIN quest as q3 ON q3.id = quest3
+             WHERE pid = $1",
+    )
+    .bind(pid)
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    for (i, id) in [q1, q2, q3].iter().enumerate() {
+        assert_eq!(item(244 + i * 12)[0] != "0", id.is_some(), "{resp:?}");
+    }
+
+    let resp = client.req(&server, "PlayerLookAt", "Alice").await;
+    let look_at = resp.values("otherplayer.playerlookat");
+    assert_eq!(look_at[39 + 8 * 12], "1");
+}
+
+#[tokio::test]
+async fn move_items() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let silver = resp.int("resources", 2);
+    // A warrior weapon and a mage hat
+    server.give_item(pid, 1, 1, 0).await;
+    server.give_item(pid, 2, 6, 1).await;
+
+    const WEAPON: usize = 48 + 8 * 12;
+    const BAG: usize = 168;
+
+    let resp = client.req(&server, "PlayerItemMove", "2/1/1/9").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, WEAPON), 1, "weapon should be equipped");
+    assert_eq!(resp.int(SAVE, BAG), 0, "bag slot should be empty");
+
+    for (args, reason) in [
+        ("2/2/1/1", "warriors can not wear mage hats"),
+        ("2/2/1/9", "hats are no weapons"),
+        ("1/9/2/2", "the hat would end up in the weapon slot"),
+        ("2/1/1/1", "there is no item to move"),
+        ("2/6/2/1", "the bag only has 5 slots"),
+    ] {
+        let resp = client.req(&server, "PlayerItemMove", args).await;
+        assert_eq!(resp.error(), Some("request not allowed"), "{reason}");
+    }
+
+    let resp = client.req(&server, "PlayerItemMove", "1/9/2/3").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, WEAPON), 0, "weapon should be unequipped");
+    assert_eq!(resp.int(SAVE, BAG + 2 * 12), 1);
+
+    let resp = client.req(&server, "PlayerItemMove", "2/3/2/2").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, BAG + 12), 1, "should have swapped");
+    assert_eq!(resp.int(SAVE, BAG + 2 * 12), 6, "should have swapped");
+
+    let resp = client.req(&server, "PlayerItemMove", "2/3/3/1").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, BAG + 2 * 12), 0, "hat should be sold");
+    // Shops pay a quarter of what an item costs
+    assert_eq!(resp.int("resources", 2), silver + 25);
+
+    let resp = client.req(&server, "PlayerItemMove", "2/2/0/0").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int(SAVE, BAG + 12), 0, "weapon should be destroyed");
+    assert_eq!(resp.int("resources", 2), silver + 25);
+
+    // Only the wares of both shops and the items of the quests are left
+    let items: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM item
+         WHERE id NOT IN (SELECT item FROM quest WHERE item IS NOT NULL)",
+    )
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    assert_eq!(items, 2 * 6);
+}
+
+#[tokio::test]
+async fn shops() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+
+    const WEAPON_SHOP: usize = 287;
+    const MAGIC_SHOP: usize = 360;
+    const BAG: usize = 168;
+
+    // The lowest byte of the first value is the item type
+    let typ = |resp: &GameResponse, idx: usize| resp.int(SAVE, idx) & 0xFF;
+    for i in 0..6 {
+        let weapon = typ(&resp, WEAPON_SHOP + i * 12);
+        assert!([1, 2, 3, 6].contains(&weapon), "weapon shop sells {weapon}");
+        let magic = typ(&resp, MAGIC_SHOP + i * 12);
+        assert!(
+            [4, 5, 7, 8, 9, 10].contains(&magic),
+            "magic shop sells {magic}"
+        );
+    }
+    let bought = typ(&resp, WEAPON_SHOP);
+    let price = resp.int(SAVE, WEAPON_SHOP + 10);
+
+    sqlx::query("UPDATE character SET silver = 0 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerItemMove", "3/1/2/1").await;
+    assert_eq!(resp.error(), Some("need more gold"));
+
+    sqlx::query(
+        "UPDATE character SET silver = 100000, mushrooms = 100 WHERE pid = $1",
+    )
+    .bind(pid)
+    .execute(&server.db)
+    .await
+    .unwrap();
+    let resp = client.req(&server, "PlayerItemMove", "3/1/2/1").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(typ(&resp, BAG), bought, "should be in the bag");
+    assert_eq!(resp.int(SAVE, WEAPON_SHOP), 0, "should be sold out");
+    assert_eq!(resp.int("resources", 2), 100000 - price);
+
+    let resp = client.req(&server, "PlayerItemMove", "3/1/2/2").await;
+    assert_eq!(resp.error(), Some("request not allowed"), "already bought");
+    // Wares can not be destroyed, or sold, without being bought
+    for args in ["3/2/0/0", "3/2/3/1", "4/1/0/0"] {
+        let resp = client.req(&server, "PlayerItemMove", args).await;
+        assert_eq!(resp.error(), Some("request not allowed"), "{args}");
+    }
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.int("resources", 2), 100000 - price, "nothing bought");
+    let resp = client.req(&server, "PlayerItemMove", "3/2/2/1").await;
+    assert_eq!(resp.error(), Some("inventory full"));
+
+    let mushrooms = client.req(&server, "Poll", "").await.int("resources", 1);
+    let resp = client.req(&server, "PlayerNewWares", "3").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(resp.int("resources", 1), mushrooms - 1);
+    assert_ne!(resp.int(SAVE, WEAPON_SHOP), 0, "should be restocked");
+    assert_eq!(typ(&resp, BAG), bought, "should be kept");
+
+    // Only the bought item, both shops and the quests have items
+    let items: i64 = sqlx::query_scalar(
+        "SELECT count(*) FROM item
+         WHERE id NOT IN (SELECT item FROM quest WHERE item IS NOT NULL)",
+    )
+    .fetch_one(&server.db)
+    .await
+    .unwrap();
+    assert_eq!(items, 1 + 2 * 6);
+}
+
+#[tokio::test]
+async fn shops_are_deleted_with_their_character() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let shops = || async {
+        sqlx::query_scalar::<_, i64>("SELECT count(*) FROM shop")
+            .fetch_one(&server.db)
+            .await
+            .unwrap()
+    };
+    assert_eq!(shops().await, 2);
+
+    sqlx::query("DELETE FROM character WHERE name = 'Alice'")
+        .execute(&server.db)
+        .await
+        .unwrap();
+    assert_eq!(shops().await, 0);
+}
+
+#[tokio::test]
+async fn buy_attributes() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let attributes = |resp: &GameResponse| -> Vec<i64> {
+        (30..45).map(|i| resp.int(SAVE, i)).collect()
+    };
+    assert_eq!(
+        attributes(&resp),
+        [3, 6, 8, 2, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+    assert_eq!(resp.int("attbonus1(3)", 0), 43);
+
+    sqlx::query("UPDATE character SET silver = 100000 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerAttributIncrease", "1/5").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    assert_eq!(
+        attributes(&resp),
+        [5, 6, 8, 2, 4, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]
+    );
+    assert_eq!(resp.int("resources", 2), 100000 - 43 - 68);
+    assert_eq!(resp.int("attbonus1(3)", 0), 102);
+    assert_eq!(resp.int("attbonus2(3)", 0), 43);
+
+    for (args, error) in [
+        ("1/5", "request not allowed"),
+        ("6/10", "request not allowed"),
+        ("5/100000", "request not allowed"),
+    ] {
+        let resp = client.req(&server, "PlayerAttributIncrease", args).await;
+        assert_eq!(resp.error(), Some(error), "{args}");
+    }
+
+    sqlx::query("UPDATE character SET silver = 0 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerAttributIncrease", "2/7").await;
+    assert_eq!(resp.error(), Some("need more gold"));
+}
+
+#[tokio::test]
+async fn arena_fight() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+    server.create_character("Bob").await;
+
+    let mut client = TestClient::new();
+    client.login(&server, "Alice").await;
+
+    let resp = client.req(&server, "PlayerArenaFight", "Bob").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let fighters = resp.values("fightheader.fighters");
+    assert_eq!(fighters.len(), 99);
+    assert_eq!(fighters[6], "Alice");
+    assert_eq!(fighters[5 + 47 + 1], "Bob");
+    let winner: i64 = resp.get("winnerid").unwrap().parse().unwrap();
+    assert!([fighters[5], fighters[5 + 47]].contains(&&*winner.to_string()));
+    let reward = resp.values("fightresult.battlereward");
+    assert_eq!(reward.len(), 21);
+    assert_eq!(reward[4], "0", "arena fights do not reward mushrooms");
+
+    let resp = client.req(&server, "PlayerArenaFight", "Nobody").await;
+    assert_eq!(resp.error(), Some("player not found"));
+}
+
+#[tokio::test]
+async fn arena_cooldown() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+    server.create_character("Bob").await;
+
+    let mut client = TestClient::new();
+    let resp = client.login(&server, "Alice").await;
+    let pid = resp.int(SAVE, 1);
+    let mushrooms = resp.int("resources", 1);
+    assert_eq!(resp.int(SAVE, ARENA_NEXT_FREE), 0);
+
+    let resp = client.req(&server, "PlayerArenaFight", "Bob").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let resp = client.req(&server, "Poll", "").await;
+    assert!(resp.int(SAVE, ARENA_NEXT_FREE) > now());
+    assert_eq!(resp.int("resources", 1), mushrooms);
+
+    // Fighting again right away costs a mushroom
+    let resp = client.req(&server, "PlayerArenaFight", "Bob").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.int("resources", 1), mushrooms - 1);
+
+    sqlx::query("UPDATE character SET mushrooms = 0 WHERE pid = $1")
+        .bind(pid)
+        .execute(&server.db)
+        .await
+        .unwrap();
+    let resp = client.req(&server, "PlayerArenaFight", "Bob").await;
+    assert_eq!(resp.error(), Some("need more coins"));
+
+    daily_reset(&server.db, tomorrow()).await.unwrap();
+    let resp = client.req(&server, "PlayerArenaFight", "Bob").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+}
+
+#[tokio::test]
+async fn rejects_invalid_sessions() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let resp = server.cmd("Poll", "").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+
+    let resp = server.cmd("NotACommand", "").await;
+    assert_eq!(resp.error(), Some("request not allowed"));
+
+    let mut client = TestClient::new();
+    client.login(&server, "Alice").await;
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+
+    // cmd.php does not check the session, so it can not be used to skip it
+    let resp = server
+        .get(&format!(
+            "/cmd.php?req=Poll&sid={}&params=",
+            client.crypto_id
+        ))
+        .await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+
+    // Replaying a request reuses its login count
+    client.login_count -= 1;
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+
+    // Replaying the login does not hand out a new session
+    let mut replay = TestClient::new();
+    let resp = replay.try_login(&server, "Alice").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+
+    // Another client has to continue above the last login count. Logging in
+    // revokes the session of the old one
+    let mut other = TestClient::new();
+    other.login_count = 2;
+    other.login(&server, "Alice").await;
+    let resp = other.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), None, "{resp:?}");
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+
+    other.session_id = DEFAULT_SESSION_ID.to_string();
+    let resp = other.req(&server, "Poll", "").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+}
+
+#[tokio::test]
+async fn whispers() {
+    let server = TestServer::new().await;
+    server.create_character("Alice").await;
+
+    let resp = server.cmd("PlayerWhisper", "server/addworld w2").await;
+    assert_eq!(resp.error(), Some("sessionid invalid"));
+
+    let mut client = TestClient::new();
+    client.login(&server, "Alice").await;
+
+    let resp = client.req(&server, "PlayerWhisper", "Bob/hello").await;
+    assert_eq!(resp.error(), Some("request not allowed"));
+
+    // Cheats are disabled by default
+    let resp = client
+        .req(&server, "PlayerWhisper", "server/level 100")
+        .await;
+    assert_eq!(resp.error(), Some("request not allowed"));
+    let resp = client.req(&server, "Poll", "").await;
+    assert_eq!(resp.int(SAVE, 7), 1);
+}
@@ -0,0 +1,166 @@
+//! Plays the game with the sf-api client against a server running on a local
+//! port. If the client can not make sense of what we send, or its view of
+//! the character differs from the database, we are not wire compatible
+
+use std::future::IntoFuture;
+
+use base64::Engine;
+use sf_api::{
+    command::Command,
+    gamestate::{tavern::CurrentAction, GameState},
+    session::{ServerConnection, Session},
+};
+use sf_server::{
+    build_router,
+    db::{test_db, DbPool},
+    DEFAULT_CRYPTO_ID,
+};
+use sqlx::Row;
+
+const PASSWORD: &str = "secret123";
+
+struct LocalServer {
+    url: String,
+    db: DbPool,
+}
+
+impl LocalServer {
+    async fn start() -> LocalServer {
+        let db = test_db().await;
+
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::serve(listener, build_router(db.clone())).into_future(),
+        );
+
+        LocalServer {
+            url: format!("http://{addr}/"),
+            db,
+        }
+    }
+
+    async fn create_character(&self, name: &str) {
+        let args = format!(
+            "{name}/{PASSWORD}/{}@example.com/1/1/1/1,1,1,1,1,1,1,1,1",
+            name.to_lowercase()
+        );
+        let params = base64::engine::general_purpose::URL_SAFE.encode(args);
+        let body = reqwest::get(format!(
+            "{}cmd.php?req=AccountCreate&sid={DEFAULT_CRYPTO_ID}&params=\
+             {params}",
+            self.url
+        ))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+        assert!(!body.starts_with("error:"), "{body}");
+    }
+
+    async fn login(&self, name: &str) -> (Session, GameState) {
+        let connection = ServerConnection::new(&self.url).unwrap();
+        let mut session = Session::new(name, PASSWORD, connection);
+        let resp = session.login().await.unwrap();
+        let gs = GameState::new(resp).unwrap();
+        (session, gs)
+    }
+
+    /// Checks, that the client sees the same character as we store
+    async fn assert_consistent(&self, gs: &GameState) {
+        let pid = gs.character.player_id as i64;
+        let row = sqlx::query(
+            "SELECT name, level, silver, mushrooms FROM character WHERE pid = \
+             $1",
+        )
+        .bind(pid)
+        .fetch_one(&self.db)
+        .await
+        .unwrap();
+        assert_eq!(gs.character.name, row.get::<String, _>("name"));
+        assert_eq!(gs.character.level as i64, row.get::<i64, _>("level"));
+        assert_eq!(gs.character.silver as i64, row.get::<i64, _>("silver"));
+        assert_eq!(
+            gs.character.mushrooms as i64,
+            row.get::<i64, _>("mushrooms")
+        );
+
+        for (idx, quest) in gs.tavern.quests.iter().enumerate() {
+            let row = sqlx::query(&format!(
+                "SELECT length, silver, xp
+                 FROM tavern JOIN quest ON quest.id = tavern.quest{}
+                 WHERE pid = $1",
+                idx + 1
+            ))
+            .bind(pid)
+            .fetch_one(&self.db)
+            .await
+            .unwrap();
+            assert_eq!(quest.base_length as i64, row.get::<i64, _>("length"));
+            assert_eq!(quest.base_silver as i64, row.get::<i64, _>("silver"));
+            assert_eq!(quest.base_experience as i64, row.get::<i64, _>("xp"));
+        }
+
+        for (slot, item) in &gs.character.equipment.0 {
+            if let Some(item) = item {
+                assert_eq!(item.typ.equipment_slot(), Some(slot), "{item:?}");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn bot_quests() {
+    let server = LocalServer::start().await;
+    server.create_character("Botty").await;
+
+    let (mut session, mut gs) = server.login("Botty").await;
+    assert_eq!(gs.character.name, "Botty");
+    server.assert_consistent(&gs).await;
+
+    let resp = session.send_command(Command::Update).await.unwrap();
+    gs.update(resp).unwrap();
+    server.assert_consistent(&gs).await;
+
+    let silver = gs.character.silver;
+    let quest_silver = gs.tavern.quests[0].base_silver as u64;
+    let resp = session
+        .send_command(Command::StartQuest {
+            quest_pos: 0,
+            overwrite_inv: true,
+        })
+        .await
+        .unwrap();
+    gs.update(resp).unwrap();
+    assert!(
+        matches!(
+            gs.tavern.current_action,
+            CurrentAction::Quest { quest_idx: 0, .. }
+        ),
+        "{:?}",
+        gs.tavern.current_action
+    );
+
+    sqlx::query("UPDATE activity SET busy_until = 0 WHERE pid = $1")
+        .bind(gs.character.player_id as i64)
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+    let resp = session
+        .send_command(Command::FinishQuest { skip: None })
+        .await
+        .unwrap();
+    gs.update(resp).unwrap();
+    assert!(matches!(gs.tavern.current_action, CurrentAction::Idle));
+    // The quest fight can be lost, in which case there is no reward
+    assert!(
+        [silver, silver + quest_silver].contains(&gs.character.silver),
+        "{} + {quest_silver} != {}",
+        silver,
+        gs.character.silver
+    );
+    server.assert_consistent(&gs).await;
+}